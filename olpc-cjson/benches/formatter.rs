@@ -0,0 +1,159 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Benchmarks `CanonicalFormatter` serializing a targets.json-like document, against three
+//! baselines: the implementation it replaced, plain `serde_json` output, which is as fast as
+//! serialization gets but isn't sorted, and sorting by converting to a `serde_json::Value` first.
+//!
+//! Criterion only measures time, so the peak memory each one uses is printed before it runs.
+//!
+//! Run with `cargo bench -p olpc-cjson`.
+
+mod legacy;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The system allocator, keeping track of the most memory allocated at once.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Returns the most memory `f` had allocated at once, including the output it returns.
+fn peak_memory<F: FnOnce() -> Vec<u8>>(f: F) -> usize {
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    let output = f();
+    let peak = PEAK.load(Ordering::SeqCst) - base;
+    drop(output);
+    peak
+}
+
+/// The `signed` part of a targets.json, serialized the way `#[derive(Serialize)]` would serialize
+/// `tough::schema::Targets`: struct fields in declaration order, and targets in a `HashMap`, so
+/// they arrive in no particular order.
+struct Targets {
+    targets: HashMap<String, Value>,
+}
+
+impl Targets {
+    fn new(count: usize) -> Self {
+        let targets = (0..count)
+            .map(|i| {
+                (
+                    format!("target-{}.img", i),
+                    json!({
+                        "length": i * 1024,
+                        "hashes": {
+                            "sha256": format!("{:064x}", i),
+                        },
+                        "custom": {
+                            "version": format!("1.{}.0", i),
+                            "file_permissions": "0644",
+                        },
+                    }),
+                )
+            })
+            .collect();
+        Self { targets }
+    }
+}
+
+impl Serialize for Targets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Targets", 5)?;
+        state.serialize_field("_type", "targets")?;
+        state.serialize_field("spec_version", "1.0.0")?;
+        state.serialize_field("version", &1)?;
+        state.serialize_field("expires", "2030-01-01T00:00:00Z")?;
+        state.serialize_field("targets", &self.targets)?;
+        state.end()
+    }
+}
+
+/// One way of serializing `Targets` to benchmark.
+type Run = fn(&Targets) -> Vec<u8>;
+
+fn serialize<F: serde_json::ser::Formatter, T: Serialize>(value: &T, formatter: F) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    value.serialize(&mut ser).unwrap();
+    buf
+}
+
+fn formatter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("targets");
+    for count in &[1_000, 100_000] {
+        let value = Targets::new(*count);
+        let output = serialize(&value, olpc_cjson::CanonicalFormatter::new());
+        // The document has no floats or escapes, so sorting its keys is all that's needed.
+        assert_eq!(
+            output,
+            serde_json::to_vec(&serde_json::to_value(&value).unwrap()).unwrap(),
+            "output is not canonical"
+        );
+        assert_eq!(
+            output,
+            serialize(&value, legacy::CanonicalFormatter::new()),
+            "formatters disagree"
+        );
+
+        group.throughput(Throughput::Bytes(output.len() as u64));
+        let benches: [(&str, Run); 4] = [
+            ("canonical", |value| {
+                serialize(value, olpc_cjson::CanonicalFormatter::new())
+            }),
+            ("legacy", |value| {
+                serialize(value, legacy::CanonicalFormatter::new())
+            }),
+            ("unsorted", |value| serde_json::to_vec(value).unwrap()),
+            ("via-value", |value| {
+                serde_json::to_vec(&serde_json::to_value(value).unwrap()).unwrap()
+            }),
+        ];
+        for (name, run) in &benches {
+            println!(
+                "targets/{}/{}: peak memory {} bytes",
+                name,
+                count,
+                peak_memory(|| run(&value))
+            );
+            group.bench_with_input(BenchmarkId::new(*name, count), &value, |b, value| {
+                b.iter(|| run(value))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = formatter
+}
+criterion_main!(benches);
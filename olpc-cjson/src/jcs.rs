@@ -0,0 +1,593 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A [`Formatter`] for the JSON Canonicalization Scheme (JCS), [RFC 8785].
+//!
+//! [`Formatter`]: ../serde_json/ser/trait.Formatter.html
+//! [RFC 8785]: https://tools.ietf.org/html/rfc8785
+//!
+//! JCS differs from OLPC canonical JSON in a few ways:
+//!
+//! * Numbers are serialized the way ECMAScript serializes IEEE 754 doubles, so floating point
+//!   numbers are allowed, and integers outside of &plusmn;2<sup>53</sup> lose precision.
+//! * Strings are escaped as in ordinary JSON, and are not normalized.
+//! * Object keys are sorted by their UTF-16 code units, not their UTF-8 bytes.
+
+use crate::{CanonicalFormatter, Unsorted, Writer};
+use serde_json::ser::{CharEscape, CompactFormatter, Formatter};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::ops::Range;
+
+/// A [`Formatter`] that produces JSON canonicalized according to [RFC 8785].
+///
+/// ```rust
+/// use olpc_cjson::JcsFormatter;
+/// use serde::Serialize;
+/// use serde_json::json;
+///
+/// let value = json!({"b": 1e30, "a": [4.50, 2e-3], "\u{20ac}": "\n"});
+/// let mut buf = Vec::new();
+/// let mut ser = serde_json::Serializer::with_formatter(&mut buf, JcsFormatter::new());
+/// value.serialize(&mut ser).unwrap();
+/// assert_eq!(buf, "{\"a\":[4.5,0.002],\"b\":1e+30,\"\u{20ac}\":\"\\n\"}".as_bytes());
+/// ```
+///
+/// [`Formatter`]: ../serde_json/ser/trait.Formatter.html
+/// [RFC 8785]: https://tools.ietf.org/html/rfc8785
+#[derive(Debug, Default)]
+pub struct JcsFormatter {
+    /// Everything inside the outermost object, which can't be written out until all of its keys
+    /// are known.
+    buf: Vec<u8>,
+    object_stack: Vec<Object>,
+    /// The finished objects in `buf` whose keys arrived out of order.
+    unsorted: Vec<Unsorted>,
+}
+
+/// An object in progress of being built. This works like `CanonicalFormatter`'s `Object`, except
+/// that keys are compared by the UTF-16 encoding of their unescaped value, which is collected as
+/// the key is written. As there, nothing in the buffer is moved or copied; objects whose keys
+/// arrived out of order are written in sorted order when the outermost object ends.
+#[derive(Debug)]
+struct Object {
+    /// Where the object's opening brace is in the buffer.
+    start: usize,
+    entries: Vec<Entry>,
+    /// Whether every key so far was greater than the one before it.
+    sorted: bool,
+    /// Whether a key is being written (as opposed to a value).
+    in_key: bool,
+}
+
+/// Where an object's key and value are in `JcsFormatter::buf`, and the key's UTF-16 code units.
+#[derive(Debug, Default)]
+struct Entry {
+    key: Range<usize>,
+    value: Range<usize>,
+    sort_key: Vec<u16>,
+}
+
+impl JcsFormatter {
+    /// Create a new `JcsFormatter` object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the buffer if we are currently writing an object, or `writer` if not.
+    fn writer<'a, W: Write + ?Sized>(&'a mut self, writer: &'a mut W) -> Writer<'a, W> {
+        if self.object_stack.is_empty() {
+            Writer::Passthrough(writer)
+        } else {
+            Writer::Buffer(&mut self.buf)
+        }
+    }
+
+    /// Returns a mutable reference to the top of the object stack.
+    fn obj_mut(&mut self) -> Result<&mut Object> {
+        self.object_stack.last_mut().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "serde_json called an object method without calling begin_object first",
+            )
+        })
+    }
+
+    /// Returns a mutable reference to the entry currently being written.
+    fn entry_mut(&mut self) -> Result<&mut Entry> {
+        self.obj_mut()?.entries.last_mut().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "serde_json called an object method without calling begin_object_key first",
+            )
+        })
+    }
+
+    /// Returns whether an object key is being written.
+    fn in_key(&self) -> bool {
+        match self.object_stack.last() {
+            Some(object) => object.in_key,
+            None => false,
+        }
+    }
+
+    /// Writes text that isn't escaped, recording it if it is part of a key.
+    fn write_text<W: Write + ?Sized>(&mut self, writer: &mut W, text: &str) -> Result<()> {
+        self.key_fragment(text);
+        self.writer(writer).write_all(text.as_bytes())
+    }
+
+    /// Records part of a string, if it is part of a key.
+    fn key_fragment(&mut self, fragment: &str) {
+        if let Some(object) = self.object_stack.last_mut() {
+            if object.in_key {
+                if let Some(entry) = object.entries.last_mut() {
+                    entry.sort_key.extend(fragment.encode_utf16());
+                }
+            }
+        }
+    }
+
+    /// Sorts the entries of an object whose keys arrived out of order. When a key appears more
+    /// than once, the last value wins.
+    fn sort_object(&mut self, object: Object) {
+        let mut entries = object.entries;
+        // Duplicate keys stay in the order they were written.
+        entries.sort_unstable_by(|a, b| {
+            a.sort_key
+                .cmp(&b.sort_key)
+                .then(a.key.start.cmp(&b.key.start))
+        });
+        entries.dedup_by(|later, earlier| {
+            let duplicate = later.sort_key == earlier.sort_key;
+            if duplicate {
+                std::mem::swap(later, earlier);
+            }
+            duplicate
+        });
+
+        self.unsorted.push(Unsorted {
+            range: object.start..self.buf.len(),
+            entries: entries
+                .into_iter()
+                .map(|entry| crate::Entry {
+                    key: entry.key,
+                    value: entry.value,
+                })
+                .collect(),
+        });
+    }
+}
+
+/// Wraps `serde_json::CompactFormatter` to use the appropriate writer (see
+/// `JcsFormatter::writer`).
+macro_rules! wrapper {
+    ($f:ident) => {
+        fn $f<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<()> {
+            CompactFormatter.$f(&mut self.writer(writer))
+        }
+    };
+
+    ($f:ident, $t:ty) => {
+        fn $f<W: Write + ?Sized>(&mut self, writer: &mut W, arg: $t) -> Result<()> {
+            CompactFormatter.$f(&mut self.writer(writer), arg)
+        }
+    };
+}
+
+/// Every JSON number is an IEEE 754 double as far as JCS is concerned. Integer map keys are
+/// strings, though, so they are written exactly, as `serde_json` writes them.
+macro_rules! number {
+    ($f:ident, $t:ty) => {
+        #[allow(clippy::cast_lossless, clippy::cast_precision_loss)]
+        fn $f<W: Write + ?Sized>(&mut self, writer: &mut W, value: $t) -> Result<()> {
+            if self.in_key() {
+                self.write_text(writer, &value.to_string())
+            } else {
+                self.write_f64(writer, value as f64)
+            }
+        }
+    };
+}
+
+impl Formatter for JcsFormatter {
+    wrapper!(write_null);
+
+    wrapper!(write_bool, bool);
+
+    number!(write_i8, i8);
+    number!(write_i16, i16);
+    number!(write_i32, i32);
+    number!(write_i64, i64);
+    number!(write_u8, u8);
+    number!(write_u16, u16);
+    number!(write_u32, u32);
+    number!(write_u64, u64);
+
+    fn write_f32<W: Write + ?Sized>(&mut self, writer: &mut W, value: f32) -> Result<()> {
+        self.write_f64(writer, f64::from(value))
+    }
+
+    // serde_json itself writes NaN and infinite values as `null` without calling this method, but
+    // other callers might not.
+    fn write_f64<W: Write + ?Sized>(&mut self, writer: &mut W, value: f64) -> Result<()> {
+        if !value.is_finite() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "NaN and Infinity are not allowed in JSON",
+            ));
+        }
+        self.write_text(writer, &ecmascript_number(value))
+    }
+
+    // This is used for u128/i128, and for all numbers with serde_json's `arbitrary_precision`
+    // feature.
+    fn write_number_str<W: Write + ?Sized>(&mut self, writer: &mut W, value: &str) -> Result<()> {
+        if self.in_key() {
+            return self.write_text(writer, value);
+        }
+        let value = value
+            .parse()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        self.write_f64(writer, value)
+    }
+
+    wrapper!(begin_string);
+    wrapper!(end_string);
+
+    fn write_string_fragment<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> Result<()> {
+        self.key_fragment(fragment);
+        CompactFormatter.write_string_fragment(&mut self.writer(writer), fragment)
+    }
+
+    // serde_json escapes exactly the characters RFC 8785 requires, in the same way.
+    fn write_char_escape<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        char_escape: CharEscape,
+    ) -> Result<()> {
+        let ch = match char_escape {
+            CharEscape::Quote => '"',
+            CharEscape::ReverseSolidus => '\\',
+            CharEscape::Solidus => '/',
+            CharEscape::Backspace => '\x08',
+            CharEscape::FormFeed => '\x0c',
+            CharEscape::LineFeed => '\n',
+            CharEscape::CarriageReturn => '\r',
+            CharEscape::Tab => '\t',
+            CharEscape::AsciiControl(byte) => char::from(byte),
+        };
+        self.key_fragment(ch.encode_utf8(&mut [0; 4]));
+        CompactFormatter.write_char_escape(&mut self.writer(writer), char_escape)
+    }
+
+    wrapper!(begin_array);
+    wrapper!(end_array);
+    wrapper!(begin_array_value, bool); // hack: this passes through the `first` argument
+    wrapper!(end_array_value);
+
+    fn begin_object<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        self.object_stack.push(Object {
+            start: self.buf.len(),
+            entries: Vec::new(),
+            sorted: true,
+            in_key: false,
+        });
+        CompactFormatter.begin_object(&mut self.buf)
+    }
+
+    fn end_object<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<()> {
+        let object = self.object_stack.pop().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "serde_json called Formatter::end_object object method
+                 without calling begin_object first",
+            )
+        })?;
+        CompactFormatter.end_object(&mut self.buf)?;
+        if !object.sorted {
+            self.sort_object(object);
+        }
+
+        if self.object_stack.is_empty() {
+            self.unsorted
+                .sort_unstable_by_key(|object| object.range.start);
+            CanonicalFormatter::write_range(&self.buf, &self.unsorted, 0..self.buf.len(), writer)?;
+            self.buf.clear();
+            self.unsorted.clear();
+        }
+        Ok(())
+    }
+
+    fn begin_object_key<W: Write + ?Sized>(&mut self, _writer: &mut W, first: bool) -> Result<()> {
+        CompactFormatter.begin_object_key(&mut self.buf, first)?;
+        let start = self.buf.len();
+        let object = self.obj_mut()?;
+        object.in_key = true;
+        object.entries.push(Entry {
+            key: start..start,
+            ..Entry::default()
+        });
+        Ok(())
+    }
+
+    fn end_object_key<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        let end = self.buf.len();
+        self.entry_mut()?.key.end = end;
+        self.obj_mut()?.in_key = false;
+        Ok(())
+    }
+
+    fn begin_object_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        CompactFormatter.begin_object_value(&mut self.buf)?;
+        let start = self.buf.len();
+        self.entry_mut()?.value = start..start;
+        Ok(())
+    }
+
+    fn end_object_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        let end = self.buf.len();
+        self.entry_mut()?.value.end = end;
+
+        let object = self.obj_mut()?;
+        if let [.., previous, current] = object.entries.as_slice() {
+            if previous.sort_key >= current.sort_key {
+                object.sorted = false;
+            }
+        }
+        Ok(())
+    }
+
+    // serde_json's `raw_value` feature passes JSON through as-is, so it has to be parsed and
+    // canonicalized like anything else.
+    fn write_raw_fragment<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> Result<()> {
+        use serde::Serialize;
+
+        let mut ser = serde_json::Serializer::with_formatter(self.writer(writer), Self::new());
+        serde_json::from_str::<serde_json::Value>(fragment)?.serialize(&mut ser)?;
+        Ok(())
+    }
+}
+
+/// Formats a finite number the way ECMAScript's `Number.prototype.toString` does (ECMA-262,
+/// section 7.1.12.1), as RFC 8785 requires.
+fn ecmascript_number(value: f64) -> String {
+    if value == 0.0 {
+        // This includes -0.
+        return "0".to_owned();
+    }
+
+    // Rust's `{:e}` formatting gives the shortest digits that round-trip, which is what
+    // ECMAScript asks for: for example, "1.2345e-7" or "5e-324".
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_at(
+        scientific
+            .find('e')
+            .expect("{:e} always includes an exponent"),
+    );
+    let exponent: i32 = exponent[1..].parse().expect("{:e} exponent is an integer");
+    let digits = round_half_even(mantissa.replace('.', ""), exponent, value.abs());
+    // In ECMA-262's terms, the value is `digits` x 10^(n - k).
+    let k = digits.len();
+    let n = exponent + 1;
+
+    let mut out = String::new();
+    if value < 0.0 {
+        out.push('-');
+    }
+    match usize::try_from(n) {
+        Ok(n) if k <= n && n <= 21 => {
+            out.push_str(&digits);
+            out.push_str(&"0".repeat(n - k));
+        }
+        Ok(n) if 0 < n && n <= 21 => {
+            out.push_str(&digits[..n]);
+            out.push('.');
+            out.push_str(&digits[n..]);
+        }
+        _ if -6 < n && n <= 0 => {
+            out.push_str("0.");
+            out.push_str(&"0".repeat(n.unsigned_abs() as usize));
+            out.push_str(&digits);
+        }
+        _ => {
+            out.push_str(&digits[..1]);
+            if k > 1 {
+                out.push('.');
+                out.push_str(&digits[1..]);
+            }
+            out.push_str(if n > 0 { "e+" } else { "e-" });
+            out.push_str(&(n - 1).abs().to_string());
+        }
+    }
+    out
+}
+
+/// When the shortest round-tripping digits are exactly halfway between two candidates, Rust picks
+/// the larger one, but ECMAScript picks the one with an even last digit. For example,
+/// 1424953923781206.25 is written as "1424953923781206.2", not "1424953923781206.3".
+fn round_half_even(mut digits: String, exponent: i32, value: f64) -> String {
+    let last = digits.as_bytes()[digits.len() - 1];
+    if last % 2 == 0 || digits == "1" {
+        return digits;
+    }
+    let mut lower = digits[..digits.len() - 1].to_owned();
+    lower.push(char::from(last - 1));
+
+    // Every double has an exact decimal expansion of fewer than 800 significant digits.
+    let exact = format!("{:.800e}", value);
+    let (exact_mantissa, exact_exponent) =
+        exact.split_at(exact.find('e').expect("{:e} always includes an exponent"));
+    let exact_digits = exact_mantissa.replace('.', "");
+    if exact_exponent[1..].parse() == Ok(exponent)
+        && exact_digits.trim_end_matches('0') == format!("{}5", lower)
+    {
+        digits = lower;
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::JcsFormatter;
+    use serde::Serialize;
+    use serde_json::Serializer;
+    use std::io::Result;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut ser = Serializer::with_formatter(&mut buf, JcsFormatter::new());
+        value.serialize(&mut ser)?;
+        Ok(buf)
+    }
+
+    /// RFC 8785, appendix B: sample IEEE 754 values and their JCS serialization.
+    #[test]
+    fn rfc8785_numbers() -> Result<()> {
+        let samples: &[(u64, &str)] = &[
+            (0x0000_0000_0000_0000, "0"),
+            (0x8000_0000_0000_0000, "0"),
+            (0x0000_0000_0000_0001, "5e-324"),
+            (0x8000_0000_0000_0001, "-5e-324"),
+            (0x7fef_ffff_ffff_ffff, "1.7976931348623157e+308"),
+            (0xffef_ffff_ffff_ffff, "-1.7976931348623157e+308"),
+            (0x4340_0000_0000_0000, "9007199254740992"),
+            (0xc340_0000_0000_0000, "-9007199254740992"),
+            (0x4430_0000_0000_0000, "295147905179352830000"),
+            (0x44b5_2d02_c7e1_4af5, "9.999999999999997e+22"),
+            (0x44b5_2d02_c7e1_4af6, "1e+23"),
+            (0x44b5_2d02_c7e1_4af7, "1.0000000000000001e+23"),
+            (0x444b_1ae4_d6e2_ef4e, "999999999999999700000"),
+            (0x444b_1ae4_d6e2_ef4f, "999999999999999900000"),
+            (0x444b_1ae4_d6e2_ef50, "1e+21"),
+            (0x3eb0_c6f7_a0b5_ed8c, "9.999999999999997e-7"),
+            (0x3eb0_c6f7_a0b5_ed8d, "0.000001"),
+            (0x41b3_de43_5555_5553, "333333333.3333332"),
+            (0x41b3_de43_5555_5554, "333333333.33333325"),
+            (0x41b3_de43_5555_5555, "333333333.3333333"),
+            (0x41b3_de43_5555_5556, "333333333.3333334"),
+            (0x41b3_de43_5555_5557, "333333333.33333343"),
+            (0xbecb_f647_612f_3696, "-0.0000033333333333333333"),
+            (0x4314_3ff3_c1cb_0959, "1424953923781206.2"),
+        ];
+        for (bits, expected) in samples {
+            assert_eq!(
+                encode(&f64::from_bits(*bits))?,
+                expected.as_bytes(),
+                "{:#018x}",
+                bits
+            );
+        }
+        Ok(())
+    }
+
+    /// RFC 8785, section 3.2.2.
+    #[test]
+    #[allow(clippy::excessive_precision)]
+    fn rfc8785_example() -> Result<()> {
+        // serde_json's float parsing isn't exact unless its `float_roundtrip` feature is enabled,
+        // so the numbers come from Rust literals instead.
+        let mut value: serde_json::Value = serde_json::from_str(
+            r#"{
+                "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals": [null, true, false]
+            }"#,
+        )?;
+        value["numbers"] = serde_json::json!([
+            333_333_333.333_333_29,
+            1E30,
+            4.50,
+            2e-3,
+            0.000_000_000_000_000_000_000_000_001
+        ]);
+        assert_eq!(
+            encode(&value)?,
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#.as_bytes()
+        );
+        Ok(())
+    }
+
+    /// RFC 8785, section 3.2.3: keys are sorted by UTF-16 code units, so U+1F600 (encoded as
+    /// surrogates starting with 0xD83D) sorts before U+FB33.
+    #[test]
+    fn rfc8785_sorting() -> Result<()> {
+        use serde::Serializer as _;
+
+        let mut buf = Vec::new();
+        let mut ser = Serializer::with_formatter(&mut buf, JcsFormatter::new());
+        (&mut ser).collect_map(vec![
+            ("\u{20ac}", "Euro Sign"),
+            ("\r", "Carriage Return"),
+            ("\u{fb33}", "Hebrew Letter Dalet With Dagesh"),
+            ("1", "One"),
+            ("\u{1f600}", "Emoji: Grinning Face"),
+            ("\u{80}", "Control"),
+            ("\u{f6}", "Latin Small Letter O With Diaeresis"),
+        ])?;
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\
+             \"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\
+             \"\u{1f600}\":\"Emoji: Grinning Face\",\
+             \"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn integers() -> Result<()> {
+        assert_eq!(encode(&0_u8)?, b"0");
+        assert_eq!(encode(&-42_i32)?, b"-42");
+        assert_eq!(encode(&1_000_000_u64)?, b"1000000");
+        // Integers beyond 2^53 are rounded to the nearest double.
+        assert_eq!(encode(&u64::MAX)?, b"18446744073709552000");
+        Ok(())
+    }
+
+    /// Map keys are strings, so integer keys are written exactly and sorted as strings.
+    #[test]
+    fn integer_keys() -> Result<()> {
+        use std::collections::{BTreeMap, HashMap};
+
+        let map: BTreeMap<u32, &str> = vec![(1, "a"), (2, "b"), (10, "c")].into_iter().collect();
+        assert_eq!(encode(&map)?, br#"{"1":"a","10":"c","2":"b"}"#);
+
+        let map: HashMap<i64, u64> = vec![(1 << 60, 1 << 60), (-5, 0)].into_iter().collect();
+        assert_eq!(
+            encode(&map)?,
+            br#"{"-5":0,"1152921504606846976":1152921504606847000}"#
+        );
+        Ok(())
+    }
+
+    /// Objects whose keys arrive out of order can be nested in each other and in arrays, and
+    /// can be dropped as duplicate keys. The inner objects here arrive in UTF-8 order, which
+    /// differs from UTF-16 order for their keys.
+    #[test]
+    fn nested_unordered_maps() -> Result<()> {
+        use serde::Serializer as _;
+        use serde_json::json;
+
+        let inner = json!({"\u{fb33}": 1, "\u{1f600}": [{"\u{fb33}": 2, "\u{1f600}": 3}]});
+        let mut buf = Vec::new();
+        let mut ser = Serializer::with_formatter(&mut buf, JcsFormatter::new());
+        (&mut ser).collect_map(vec![
+            ("b", json!({"\u{fb33}": 0, "\u{1f600}": 0})),
+            ("a", inner),
+            ("b", json!(null)),
+        ])?;
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"a\":{\"\u{1f600}\":[{\"\u{1f600}\":3,\"\u{fb33}\":2}],\"\u{fb33}\":1},\"b\":null}"
+        );
+        Ok(())
+    }
+}
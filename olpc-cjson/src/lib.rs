@@ -11,13 +11,23 @@
 //! OLPC's canonical JSON specification is subtly different from other "canonical JSON"
 //! specifications, and is also not a strict subset of JSON (specifically, ASCII control characters
 //! 0x00&ndash;0x1f are printed literally, which is not valid JSON). Therefore, `serde_json` cannot
-//! necessarily deserialize JSON produced by this formatter.
+//! necessarily deserialize JSON produced by this formatter. Use [`from_slice`] to read it back in,
+//! and [`is_canonical`] to check that a document is already in canonical form.
+//!
+//! [`from_slice`]: fn.from_slice.html
+//! [`is_canonical`]: fn.is_canonical.html
 //!
 //! This crate is not developed or endorsed by OLPC; use of the term is solely to distinguish this
 //! specification of canonical JSON from [other specifications of canonical JSON][xkcd].
 //!
 //! [xkcd]: https://xkcd.com/927/
 //!
+//! For interoperating with systems that use the JSON Canonicalization Scheme ([RFC 8785])
+//! instead, this crate also provides [`JcsFormatter`].
+//!
+//! [RFC 8785]: https://tools.ietf.org/html/rfc8785
+//! [`JcsFormatter`]: struct.JcsFormatter.html
+//!
 //! ```rust
 //! use olpc_cjson::CanonicalFormatter;
 //! use serde::Serialize;
@@ -33,10 +43,16 @@
 #![deny(rust_2018_idioms)]
 #![warn(clippy::pedantic)]
 
+mod jcs;
+mod parse;
+
+pub use crate::jcs::JcsFormatter;
+pub use crate::parse::{from_slice, is_canonical};
+
 use serde::Serialize;
 use serde_json::ser::{CharEscape, CompactFormatter, Formatter, Serializer};
-use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind, Result, Write};
+use std::ops::Range;
 use unicode_normalization::UnicodeNormalization;
 
 /// A [`Formatter`] that produces canonical JSON.
@@ -46,34 +62,63 @@ use unicode_normalization::UnicodeNormalization;
 /// [`Formatter`]: ../serde_json/ser/trait.Formatter.html
 #[derive(Debug, Default)]
 pub struct CanonicalFormatter {
+    /// Everything inside the outermost object, which can't be written out until all of its keys
+    /// are known.
+    buf: Vec<u8>,
     object_stack: Vec<Object>,
+    /// The finished objects in `buf` whose keys arrived out of order.
+    unsorted: Vec<Unsorted>,
 }
 
 /// Internal struct to keep track of an object in progress of being built.
 ///
-/// As keys and values are received by `CanonicalFormatter`, they are written to `next_key` and
-/// `next_value` by using the `CanonicalFormatter::writer` convenience method.
+/// Objects are written to `CanonicalFormatter::buf` in the order `serde_json` provides their
+/// entries, and `entries` records where each key and value were written.
 ///
 /// How this struct behaves when `Formatter` methods are called:
 ///
 /// ```plain
-/// [other methods]  // values written to the writer received by method
-/// begin_object     // create this object
-/// /-> begin_object_key    // object.key_done = false;
-/// |   [other methods]     // values written to object.next_key, writer received by method ignored
-/// |   end_object_key      // object.key_done = true;
-/// |   begin_object_value  // [nothing]
-/// |   [other methods]     // values written to object.next_value
-/// |   end_object_value    // object.next_key and object.next_value are inserted into object.obj
+/// [other methods]  // values written to the writer received by method, or to the buffer
+/// begin_object     // create this object, starting at the end of the buffer
+/// /-> begin_object_key    // write a comma if this isn't the first entry; the key starts here
+/// |   [other methods]     // key written to the buffer
+/// |   end_object_key      // the key ends here
+/// |   begin_object_value  // write a colon; the value starts here
+/// |   [other methods]     // value written to the buffer
+/// |   end_object_value    // the value ends here; check the keys are still in order
 /// \---- // jump back if more values are present
-/// end_object       // write the object (sorted by its keys) to the writer received by the method
+/// end_object       // if the keys arrived out of order, record the object's entries sorted by
+///                  // key; if this is the outermost object, write the buffer to the writer,
+///                  // following the sorted entries of any objects that need them
 /// ```
-#[derive(Debug, Default)]
+///
+/// Nothing in the buffer is ever moved or copied. Objects whose keys arrive out of order, such as
+/// structs (whose fields arrive in declaration order) and `HashMap`s, cost an extra 32 bytes per
+/// entry until the outermost object is written. Peak memory is the size of the outermost object's
+/// output (twice that while the buffer grows), plus those entries.
+#[derive(Debug)]
 struct Object {
-    obj: BTreeMap<Vec<u8>, Vec<u8>>,
-    next_key: Vec<u8>,
-    next_value: Vec<u8>,
-    key_done: bool,
+    /// Where the object's opening brace is in the buffer.
+    start: usize,
+    entries: Vec<Entry>,
+    /// Whether every key so far was greater than the one before it.
+    sorted: bool,
+}
+
+/// Where an object's key and value are in `CanonicalFormatter::buf`. The key includes its quotes.
+#[derive(Debug, Default)]
+struct Entry {
+    key: Range<usize>,
+    value: Range<usize>,
+}
+
+/// A finished object whose keys arrived out of order.
+#[derive(Debug)]
+struct Unsorted {
+    /// Where the object is in `CanonicalFormatter::buf`, including its braces.
+    range: Range<usize>,
+    /// The object's entries sorted by key, without duplicate keys.
+    entries: Vec<Entry>,
 }
 
 impl CanonicalFormatter {
@@ -85,19 +130,14 @@ impl CanonicalFormatter {
     /// Convenience method to return the appropriate writer given the current context.
     ///
     /// If we are currently writing an object (that is, if `!self.object_stack.is_empty()`), we
-    /// need to write the value to either the next key or next value depending on that state
-    /// machine. See the docstrings for `Object` for more detail.
+    /// need to write to the buffer. See the docstrings for `Object` for more detail.
     ///
     /// If we are not currently writing an object, pass through `writer`.
-    fn writer<'a, W: Write + ?Sized>(&'a mut self, writer: &'a mut W) -> Box<dyn Write + 'a> {
-        if let Some(object) = self.object_stack.last_mut() {
-            if object.key_done {
-                Box::new(&mut object.next_value)
-            } else {
-                Box::new(&mut object.next_key)
-            }
+    fn writer<'a, W: Write + ?Sized>(&'a mut self, writer: &'a mut W) -> Writer<'a, W> {
+        if self.object_stack.is_empty() {
+            Writer::Passthrough(writer)
         } else {
-            Box::new(writer)
+            Writer::Buffer(&mut self.buf)
         }
     }
 
@@ -110,6 +150,104 @@ impl CanonicalFormatter {
             )
         })
     }
+
+    /// Returns a mutable reference to the entry currently being written.
+    fn entry_mut(&mut self) -> Result<&mut Entry> {
+        self.obj_mut()?.entries.last_mut().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "serde_json called an object method without calling begin_object_key first",
+            )
+        })
+    }
+
+    /// Sorts the entries of an object whose keys arrived out of order. When a key appears more
+    /// than once, the last value wins, as it would if the object were collected into a map.
+    fn sort_object(&mut self, object: Object) {
+        let buf = &self.buf;
+        let mut entries = object.entries;
+        // Duplicate keys stay in the order they were written. Unlike `sort_by`, this doesn't
+        // allocate.
+        entries.sort_unstable_by(|a, b| {
+            buf[a.key.clone()]
+                .cmp(&buf[b.key.clone()])
+                .then(a.key.start.cmp(&b.key.start))
+        });
+        entries.dedup_by(|later, earlier| {
+            let duplicate = buf[later.key.clone()] == buf[earlier.key.clone()];
+            if duplicate {
+                std::mem::swap(later, earlier);
+            }
+            duplicate
+        });
+
+        self.unsorted.push(Unsorted {
+            range: object.start..self.buf.len(),
+            entries,
+        });
+    }
+
+    /// Writes `range` of the buffer, writing any unsorted objects within it in sorted order.
+    /// `unsorted` must be sorted by where the objects start.
+    fn write_range<W: Write + ?Sized>(
+        buf: &[u8],
+        unsorted: &[Unsorted],
+        range: Range<usize>,
+        writer: &mut W,
+    ) -> Result<()> {
+        let mut pos = range.start;
+        loop {
+            // Unsorted objects are nested in or disjoint from each other, so the first one that
+            // starts at or after `pos` isn't nested in another that does.
+            let next = unsorted.partition_point(|object| object.range.start < pos);
+            let object = match unsorted.get(next) {
+                Some(object) if object.range.start < range.end => object,
+                _ => break,
+            };
+            writer.write_all(&buf[pos..object.range.start])?;
+            writer.write_all(b"{")?;
+            for (i, entry) in object.entries.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b",")?;
+                }
+                writer.write_all(&buf[entry.key.clone()])?;
+                writer.write_all(b":")?;
+                Self::write_range(buf, unsorted, entry.value.clone(), writer)?;
+            }
+            writer.write_all(b"}")?;
+            pos = object.range.end;
+        }
+        writer.write_all(&buf[pos..range.end])
+    }
+}
+
+/// The writer returned by `CanonicalFormatter::writer`.
+enum Writer<'a, W: ?Sized> {
+    Buffer(&'a mut Vec<u8>),
+    Passthrough(&'a mut W),
+}
+
+impl<W: Write + ?Sized> Write for Writer<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self {
+            Writer::Buffer(vec) => vec.write(buf),
+            Writer::Passthrough(writer) => writer.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        match self {
+            Writer::Buffer(vec) => vec.write_all(buf),
+            Writer::Passthrough(writer) => writer.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Writer::Buffer(vec) => vec.flush(),
+            Writer::Passthrough(writer) => writer.flush(),
+        }
+    }
 }
 
 /// Wraps `serde_json::CompactFormatter` to use the appropriate writer (see
@@ -173,16 +311,21 @@ impl Formatter for CanonicalFormatter {
     wrapper!(end_string);
 
     // Strings are normalized as Normalization Form C (NFC). `str::nfc` is provided by the
-    // `UnicodeNormalization` trait and returns an iterator of `char`s.
+    // `UnicodeNormalization` trait and returns an iterator of `char`s. ASCII strings are already
+    // in NFC, and are written as-is.
     fn write_string_fragment<W: Write + ?Sized>(
         &mut self,
         writer: &mut W,
         fragment: &str,
     ) -> Result<()> {
-        fragment.nfc().try_for_each(|ch| {
-            self.writer(writer)
-                .write_all(ch.encode_utf8(&mut [0; 4]).as_bytes())
-        })
+        let mut writer = self.writer(writer);
+        if fragment.is_ascii() {
+            writer.write_all(fragment.as_bytes())
+        } else {
+            fragment
+                .nfc()
+                .try_for_each(|ch| writer.write_all(ch.encode_utf8(&mut [0; 4]).as_bytes()))
+        }
     }
 
     // Only quotes and backslashes are escaped in canonical JSON.
@@ -191,13 +334,14 @@ impl Formatter for CanonicalFormatter {
         writer: &mut W,
         char_escape: CharEscape,
     ) -> Result<()> {
+        let mut writer = self.writer(writer);
         match char_escape {
             CharEscape::Quote | CharEscape::ReverseSolidus => {
-                self.writer(writer).write_all(b"\\")?;
+                writer.write_all(b"\\")?;
             }
             _ => {}
         }
-        self.writer(writer).write_all(&[match char_escape {
+        writer.write_all(&[match char_escape {
             CharEscape::Quote => b'\"',
             CharEscape::ReverseSolidus => b'\\',
             CharEscape::Solidus => b'/',
@@ -215,13 +359,17 @@ impl Formatter for CanonicalFormatter {
     wrapper!(begin_array_value, bool); // hack: this passes through the `first` argument
     wrapper!(end_array_value);
 
-    // Here are the object methods. Because keys must be sorted, we serialize the object's keys and
-    // values in memory as a `BTreeMap`, then write it all out when `end_object_value` is called.
-
-    fn begin_object<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<()> {
-        CompactFormatter.begin_object(&mut self.writer(writer))?;
-        self.object_stack.push(Object::default());
-        Ok(())
+    // Here are the object methods. Objects are written to the buffer as they arrive, with the
+    // position of each key and value recorded, and sorted if necessary when `end_object` is
+    // called.
+
+    fn begin_object<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        self.object_stack.push(Object {
+            start: self.buf.len(),
+            entries: Vec::new(),
+            sorted: true,
+        });
+        CompactFormatter.begin_object(&mut self.buf)
     }
 
     fn end_object<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<()> {
@@ -232,45 +380,58 @@ impl Formatter for CanonicalFormatter {
                  without calling begin_object first",
             )
         })?;
-        let mut writer = self.writer(writer);
-        let mut first = true;
-
-        for (key, value) in object.obj {
-            CompactFormatter.begin_object_key(&mut writer, first)?;
-            writer.write_all(&key)?;
-            CompactFormatter.end_object_key(&mut writer)?;
-
-            CompactFormatter.begin_object_value(&mut writer)?;
-            writer.write_all(&value)?;
-            CompactFormatter.end_object_value(&mut writer)?;
-
-            first = false;
+        CompactFormatter.end_object(&mut self.buf)?;
+        if !object.sorted {
+            self.sort_object(object);
         }
 
-        CompactFormatter.end_object(&mut writer)
+        if self.object_stack.is_empty() {
+            self.unsorted
+                .sort_unstable_by_key(|object| object.range.start);
+            Self::write_range(&self.buf, &self.unsorted, 0..self.buf.len(), writer)?;
+            self.buf.clear();
+            self.unsorted.clear();
+        }
+        Ok(())
     }
 
-    fn begin_object_key<W: Write + ?Sized>(&mut self, _writer: &mut W, _first: bool) -> Result<()> {
-        let mut object = self.obj_mut()?;
-        object.key_done = false;
+    fn begin_object_key<W: Write + ?Sized>(&mut self, _writer: &mut W, first: bool) -> Result<()> {
+        CompactFormatter.begin_object_key(&mut self.buf, first)?;
+        let start = self.buf.len();
+        self.obj_mut()?.entries.push(Entry {
+            key: start..start,
+            value: Range::default(),
+        });
         Ok(())
     }
 
     fn end_object_key<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
-        let mut object = self.obj_mut()?;
-        object.key_done = true;
+        let end = self.buf.len();
+        self.entry_mut()?.key.end = end;
         Ok(())
     }
 
     fn begin_object_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
+        CompactFormatter.begin_object_value(&mut self.buf)?;
+        let start = self.buf.len();
+        self.entry_mut()?.value = start..start;
         Ok(())
     }
 
     fn end_object_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> Result<()> {
-        let object = self.obj_mut()?;
-        let key = std::mem::replace(&mut object.next_key, Vec::new());
-        let value = std::mem::replace(&mut object.next_value, Vec::new());
-        object.obj.insert(key, value);
+        let end = self.buf.len();
+        self.entry_mut()?.value.end = end;
+
+        let buf = &self.buf;
+        let object = self
+            .object_stack
+            .last_mut()
+            .expect("entry_mut checked for an object");
+        if let [.., previous, current] = object.entries.as_slice() {
+            if buf[previous.key.clone()] >= buf[current.key.clone()] {
+                object.sorted = false;
+            }
+        }
         Ok(())
     }
 
@@ -422,4 +583,109 @@ mod tests {
 
         Ok(())
     }
+
+    /// `serde_json::Value` always provides keys in order; maps like `HashMap` don't. Objects whose
+    /// keys arrive out of order are written sorted, keeping the last of any duplicate keys.
+    #[test]
+    fn unordered_map() -> Result<()> {
+        use serde::Serializer as _;
+
+        let mut buf = Vec::new();
+        let mut ser = Serializer::with_formatter(&mut buf, CanonicalFormatter::new());
+        (&mut ser).collect_seq(vec![vec![
+            ("b", serde_json::json!({"z": 1, "y": [{"k": 2, "j": 3}]})),
+            ("a", serde_json::json!(null)),
+            ("b", serde_json::json!(2)),
+            ("\t", serde_json::json!("")),
+        ]
+        .into_iter()
+        .collect::<std::collections::HashMap<_, _>>()])?;
+        assert_eq!(buf, b"[{\"\t\":\"\",\"a\":null,\"b\":2}]".to_vec());
+
+        let mut buf = Vec::new();
+        let mut ser = Serializer::with_formatter(&mut buf, CanonicalFormatter::new());
+        (&mut ser).collect_map(vec![
+            ("y", serde_json::json!({"z": 1, "y": [{"k": 2, "j": 3}]})),
+            ("x", serde_json::json!(1)),
+            ("y", serde_json::json!([{"k": 2, "j": 3}])),
+            ("w", serde_json::json!("\"")),
+        ])?;
+        assert_eq!(buf, br#"{"w":"\"","x":1,"y":[{"j":3,"k":2}]}"#.to_vec());
+
+        Ok(())
+    }
+
+    /// Unsorted objects nested in unsorted objects, in arrays, and in entries that are dropped as
+    /// duplicates are all written from where they are in the buffer.
+    #[test]
+    fn nested_unordered_maps() -> Result<()> {
+        use serde::ser::SerializeMap;
+
+        struct Map(Vec<(&'static str, Value)>);
+        enum Value {
+            Int(i32),
+            Map(Map),
+            Seq(Vec<Value>),
+        }
+        impl Serialize for Map {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                s: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                let mut map = s.serialize_map(Some(self.0.len()))?;
+                for (k, v) in &self.0 {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+        impl Serialize for Value {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                s: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                match self {
+                    Value::Int(i) => s.serialize_i32(*i),
+                    Value::Map(map) => map.serialize(s),
+                    Value::Seq(seq) => s.collect_seq(seq),
+                }
+            }
+        }
+
+        let value = Map(vec![
+            (
+                "d",
+                Value::Map(Map(vec![("z", Value::Int(1)), ("a", Value::Int(2))])),
+            ),
+            (
+                "c",
+                Value::Seq(vec![
+                    Value::Map(Map(vec![
+                        (
+                            "y",
+                            Value::Map(Map(vec![("q", Value::Int(3)), ("p", Value::Int(4))])),
+                        ),
+                        ("x", Value::Int(5)),
+                    ])),
+                    Value::Int(6),
+                ]),
+            ),
+            (
+                "d",
+                Value::Map(Map(vec![("n", Value::Int(7)), ("m", Value::Int(8))])),
+            ),
+            ("b", Value::Map(Map(vec![]))),
+        ]);
+        let mut buf = Vec::new();
+        value.serialize(&mut Serializer::with_formatter(
+            &mut buf,
+            CanonicalFormatter::new(),
+        ))?;
+        assert_eq!(
+            buf,
+            br#"{"b":{},"c":[{"x":5,"y":{"p":4,"q":3}},6],"d":{"m":8,"n":7}}"#.to_vec()
+        );
+
+        Ok(())
+    }
 }
@@ -1,19 +1,235 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-//! Convenience binary for reading a JSON document on stdin and outputting the canonical JSON form
-//! on stdout.
+//! Convenience binary for reading a JSON document on stdin (or from a file) and outputting the
+//! canonical JSON form on stdout, along with a few other things that come up when a signature
+//! mysteriously fails to verify.
 
 use olpc_cjson::CanonicalFormatter;
 use serde::Serialize;
-use serde_json::Serializer;
-use std::io;
+use serde_json::{Serializer, Value};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-fn main() -> Result<()> {
-    let mut ser = Serializer::with_formatter(io::stdout(), CanonicalFormatter::new());
-    let value: serde_json::Value = serde_json::from_reader(io::stdin())?;
+static USAGE: &str = "\
+Usage: olpc-cjson [OPTIONS] [FILE]
+
+Reads a JSON document from FILE (or stdin) and writes its canonical form to stdout.
+
+Options:
+    --check          Exit with status 1 if the input is not already canonical
+    --sha256         Print the SHA-256 digest of the canonical form instead of the form itself
+    --pointer PTR    Only use the subtree at JSON pointer PTR (for example, /signed)
+    --diff OTHER     Compare with OTHER after canonicalizing both, listing the JSON pointers of
+                     values that differ; exit with status 1 if there are any
+    -h, --help       Print this message
+
+Exits with status 2 on errors.";
+
+#[derive(Debug, Default)]
+struct Args {
+    check: bool,
+    sha256: bool,
+    pointer: Option<String>,
+    diff: Option<PathBuf>,
+    file: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = OsString>>(mut args: I) -> Result<Self> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("-h") | Some("--help") => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                Some("--check") => parsed.check = true,
+                Some("--sha256") => parsed.sha256 = true,
+                Some("--pointer") => {
+                    let pointer = args.next().ok_or("--pointer requires a value")?;
+                    parsed.pointer = Some(pointer.into_string().map_err(|_| "invalid pointer")?);
+                }
+                Some("--diff") => {
+                    parsed.diff = Some(args.next().ok_or("--diff requires a value")?.into());
+                }
+                Some(flag) if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option {}\n\n{}", flag, USAGE).into());
+                }
+                _ if parsed.file.is_none() => parsed.file = Some(arg.into()),
+                _ => return Err(format!("too many arguments\n\n{}", USAGE).into()),
+            }
+        }
+
+        if parsed.check && (parsed.pointer.is_some() || parsed.diff.is_some()) {
+            return Err("--check can't be combined with --pointer or --diff".into());
+        }
+        if parsed.diff.is_some() && parsed.sha256 {
+            return Err("--diff and --sha256 can't be combined".into());
+        }
+        Ok(parsed)
+    }
+
+    /// Reads the input, or stdin if there is no input file or it is `-`.
+    fn read_input(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        match &self.file {
+            Some(path) if path.as_os_str() != "-" => {
+                data = std::fs::read(path)
+                    .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+            }
+            _ => {
+                io::stdin().read_to_end(&mut data)?;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Parses a document and selects the subtree at `--pointer`, if given.
+    fn select(&self, data: &[u8], name: &str) -> Result<Value> {
+        let mut value: Value = olpc_cjson::from_slice(data)
+            .map_err(|err| format!("failed to parse {}: {}", name, err))?;
+        if let Some(pointer) = &self.pointer {
+            value = value
+                .pointer_mut(pointer)
+                .map(Value::take)
+                .ok_or_else(|| format!("{} has no value at {}", name, pointer))?;
+        }
+        Ok(value)
+    }
+}
+
+fn canonicalize(value: &Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut ser = Serializer::with_formatter(&mut buf, CanonicalFormatter::new());
     value.serialize(&mut ser)?;
+    Ok(buf)
+}
+
+/// Prints the JSON pointers at which `a` and `b` differ once canonicalized, and returns whether
+/// there were any.
+fn diff(pointer: &str, a: &Value, b: &Value, out: &mut dyn Write) -> Result<bool> {
+    // Comparing canonical forms rather than values ignores differences in Unicode normalization,
+    // which canonicalization removes.
+    let (a_canonical, b_canonical) = (canonicalize(a)?, canonicalize(b)?);
+    if a_canonical == b_canonical {
+        return Ok(false);
+    }
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys = a.keys().chain(b.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}/{}", pointer, escape_pointer_token(key));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => {
+                        diff(&child, a, b, out)?;
+                    }
+                    (Some(a), None) => print_change(out, '-', &child, a)?,
+                    (None, Some(b)) => print_change(out, '+', &child, b)?,
+                    (None, None) => unreachable!("key came from one of the objects"),
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let child = format!("{}/{}", pointer, i);
+                match (a.get(i), b.get(i)) {
+                    (Some(a), Some(b)) => {
+                        diff(&child, a, b, out)?;
+                    }
+                    (Some(a), None) => print_change(out, '-', &child, a)?,
+                    (None, Some(b)) => print_change(out, '+', &child, b)?,
+                    (None, None) => unreachable!("index is within one of the arrays"),
+                }
+            }
+        }
+        _ => {
+            print_change(out, '-', pointer, a)?;
+            print_change(out, '+', pointer, b)?;
+        }
+    }
+    Ok(true)
+}
+
+fn print_change(out: &mut dyn Write, sign: char, pointer: &str, value: &Value) -> Result<()> {
+    write!(
+        out,
+        "{} {}: ",
+        sign,
+        if pointer.is_empty() {
+            "(root)"
+        } else {
+            pointer
+        }
+    )?;
+    out.write_all(&canonicalize(value)?)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Escapes an object key for use in a JSON pointer (RFC 6901).
+fn escape_pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn main() {
+    // Like diff(1), status 1 means "not canonical" or "different", and 2 means something went
+    // wrong.
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }
+}
+
+fn run() -> Result<()> {
+    let args = Args::parse(std::env::args_os().skip(1))?;
+    let data = args.read_input()?;
+    let name = args
+        .file
+        .as_ref()
+        .map_or_else(|| "stdin".to_owned(), |path| path.display().to_string());
+
+    // Parse first, so that input that isn't JSON at all is an error rather than "not canonical".
+    let value = args.select(&data, &name)?;
+
+    if args.check {
+        if olpc_cjson::is_canonical(&data) {
+            return Ok(());
+        }
+        eprintln!("{} is not canonical JSON", name);
+        std::process::exit(1);
+    }
+
+    if let Some(other) = &args.diff {
+        let other_data = std::fs::read(other)
+            .map_err(|err| format!("failed to read {}: {}", other.display(), err))?;
+        let other_value = args.select(&other_data, &other.display().to_string())?;
+        let pointer = args.pointer.clone().unwrap_or_default();
+        let stdout = io::stdout();
+        if diff(&pointer, &value, &other_value, &mut stdout.lock())? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let canonical = canonicalize(&value)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if args.sha256 {
+        writeln!(stdout, "{}", hex(&Sha256::digest(&canonical)))?;
+    } else {
+        stdout.write_all(&canonical)?;
+    }
     Ok(())
 }
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,141 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Reading canonical JSON back in.
+//!
+//! The only part of OLPC canonical JSON that isn't also JSON is that ASCII control characters in
+//! strings are written literally. Rather than write another JSON parser, we escape those
+//! characters as `\u00XX` and hand the result to `serde_json`.
+
+use crate::CanonicalFormatter;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Serializer;
+use std::borrow::Cow;
+
+/// Deserializes an instance of type `T` from bytes of canonical JSON.
+///
+/// This accepts anything `serde_json::from_slice` does, plus literal ASCII control characters in
+/// strings. It does not check that the input is canonical; use [`is_canonical`] for that.
+///
+/// [`is_canonical`]: fn.is_canonical.html
+///
+/// ```rust
+/// let value: serde_json::Value = olpc_cjson::from_slice(b"{\"a\":\"\t\"}").unwrap();
+/// assert_eq!(value, serde_json::json!({"a": "\t"}));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the input is not valid (canonical) JSON, or if it does not match the
+/// structure expected by `T`.
+pub fn from_slice<T: DeserializeOwned>(v: &[u8]) -> serde_json::Result<T> {
+    serde_json::from_slice(&escape_control_characters(v))
+}
+
+/// Returns whether `data` is exactly the canonical JSON serialization of the value it represents:
+/// keys are sorted with no duplicates, there is no insignificant whitespace, only quotes and
+/// backslashes are escaped, strings are in Unicode Normalization Form C, and all numbers are
+/// integers.
+///
+/// ```rust
+/// assert!(olpc_cjson::is_canonical(br#"{"a":1,"b":[true,null]}"#));
+/// assert!(!olpc_cjson::is_canonical(br#"{"b":1,"a":2}"#));
+/// assert!(!olpc_cjson::is_canonical(br#"{"a": 1}"#));
+/// ```
+pub fn is_canonical(data: &[u8]) -> bool {
+    let value: serde_json::Value = match from_slice(data) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let mut buf = Vec::with_capacity(data.len());
+    let mut ser = Serializer::with_formatter(&mut buf, CanonicalFormatter::new());
+    value.serialize(&mut ser).is_ok() && buf == data
+}
+
+/// Replaces literal ASCII control characters within strings with `\u00XX` escapes. The input is
+/// only copied if there is something to replace.
+fn escape_control_characters(v: &[u8]) -> Cow<'_, [u8]> {
+    let mut escaped: Option<Vec<u8>> = None;
+    let mut in_string = false;
+    let mut in_escape = false;
+    for (i, &byte) in v.iter().enumerate() {
+        if in_string && !in_escape && byte < 0x20 {
+            let out = escaped.get_or_insert_with(|| {
+                let mut out = Vec::with_capacity(v.len() + 16);
+                out.extend_from_slice(&v[..i]);
+                out
+            });
+            out.extend_from_slice(format!("\\u{:04x}", byte).as_bytes());
+            continue;
+        }
+
+        if in_escape {
+            in_escape = false;
+        } else if in_string && byte == b'\\' {
+            in_escape = true;
+        } else if byte == b'"' {
+            in_string = !in_string;
+        }
+        if let Some(out) = &mut escaped {
+            out.push(byte);
+        }
+    }
+    match escaped {
+        Some(out) => Cow::Owned(out),
+        None => Cow::Borrowed(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{from_slice, is_canonical};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn literal_control_characters() {
+        let value: Value = from_slice(b"{\"\t\":\"\n\x00\\\"\x1f\"}").unwrap();
+        assert_eq!(value, json!({"\t": "\n\u{0}\"\u{1f}"}));
+        assert!(is_canonical(b"{\"\t\":\"\n\x00\\\"\x1f\"}"));
+
+        // Escaped control characters are still JSON, but they aren't canonical.
+        let value: Value = from_slice(br#"["\n"]"#).unwrap();
+        assert_eq!(value, json!(["\n"]));
+        assert!(!is_canonical(br#"["\n"]"#));
+
+        // A backslash at the end of a string must not hide the closing quote.
+        let value: Value = from_slice(b"[\"\\\\\",\"\x01\"]").unwrap();
+        assert_eq!(value, json!(["\\", "\u{1}"]));
+    }
+
+    #[test]
+    fn canonical() {
+        assert!(is_canonical(b"{}"));
+        assert!(is_canonical(b"[]"));
+        assert!(is_canonical(b"-5"));
+        assert!(is_canonical(br#"{"a":null,"b":[1,2,{"c":"d"}]}"#));
+        assert!(is_canonical("\"\u{e9}\"".as_bytes()));
+    }
+
+    #[test]
+    fn not_canonical() {
+        // whitespace
+        assert!(!is_canonical(b"[1, 2]"));
+        assert!(!is_canonical(b"[1,2]\n"));
+        // key order and duplicate keys
+        assert!(!is_canonical(br#"{"b":1,"a":2}"#));
+        assert!(!is_canonical(br#"{"a":1,"a":1}"#));
+        // numbers
+        assert!(!is_canonical(b"1.0"));
+        assert!(!is_canonical(b"1e3"));
+        assert!(!is_canonical(b"-0"));
+        // unnecessary escapes and non-NFC strings
+        assert!(!is_canonical(br#""\/""#));
+        assert!(!is_canonical(br#""\u0041""#));
+        assert!(!is_canonical("\"e\u{301}\"".as_bytes()));
+        // not JSON at all
+        assert!(!is_canonical(b""));
+        assert!(!is_canonical(b"{\"a\":1"));
+        assert!(!is_canonical(b"\xff"));
+    }
+}
@@ -2,30 +2,38 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::error::{self, Result};
+use crate::observer::Observer;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use snafu::ResultExt;
 use std::fs::{self, File};
 use std::io::{ErrorKind, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[derive(Debug, Clone)]
-pub(crate) struct Datastore<'a>(Arc<RwLock<&'a Path>>);
+pub(crate) struct Datastore<'a> {
+    path: Arc<RwLock<&'a Path>>,
+    observer: &'a dyn Observer,
+}
 
 impl<'a> Datastore<'a> {
-    pub(crate) fn new(path: &'a Path) -> Self {
-        Self(Arc::new(RwLock::new(path)))
+    pub(crate) fn new(path: &'a Path, observer: &'a dyn Observer) -> Self {
+        Self {
+            path: Arc::new(RwLock::new(path)),
+            observer,
+        }
     }
 
     // Because we are not actually changing the underlying data in the lock, we can ignore when a
     // lock is poisoned.
 
     fn read(&self) -> RwLockReadGuard<'_, &'a Path> {
-        self.0.read().unwrap_or_else(PoisonError::into_inner)
+        self.path.read().unwrap_or_else(PoisonError::into_inner)
     }
 
     fn write(&self) -> RwLockWriteGuard<'_, &'a Path> {
-        self.0.write().unwrap_or_else(PoisonError::into_inner)
+        self.path.write().unwrap_or_else(PoisonError::into_inner)
     }
 
     pub(crate) fn reader(&self, file: &str) -> Result<Option<impl Read>> {
@@ -41,6 +49,31 @@ impl<'a> Datastore<'a> {
 
     pub(crate) fn create<T: Serialize>(&self, file: &str, value: &T) -> Result<()> {
         let path = self.write().join(file);
+        self.write_file(file, path, value)
+    }
+
+    /// Passes the parsed contents of `file` to `f` and writes back the value it returns, holding
+    /// the write lock throughout so that concurrent updates can't interleave. A missing or
+    /// unparseable file is passed to `f` as `None`.
+    pub(crate) fn update<T, F>(&self, file: &str, f: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Result<T>,
+    {
+        let lock = self.write();
+        let path = lock.join(file);
+        let current = match File::open(&path) {
+            Ok(reader) => serde_json::from_reader(reader).ok(),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err).context(error::DatastoreOpen { path: &path }),
+        };
+        let value = f(current)?;
+        self.write_file(file, path, &value)?;
+        drop(lock);
+        Ok(value)
+    }
+
+    fn write_file<T: Serialize>(&self, file: &str, path: PathBuf, value: &T) -> Result<()> {
         serde_json::to_writer_pretty(
             File::create(&path).context(error::DatastoreCreate { path: &path })?,
             value,
@@ -48,13 +81,18 @@ impl<'a> Datastore<'a> {
         .context(error::DatastoreSerialize {
             what: format!("{} in datastore", file),
             path,
-        })
+        })?;
+        self.observer.datastore_written(file);
+        Ok(())
     }
 
     pub(crate) fn remove(&self, file: &str) -> Result<()> {
         let path = self.write().join(file);
         match fs::remove_file(&path) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                self.observer.datastore_removed(file);
+                Ok(())
+            }
             Err(err) => match err.kind() {
                 ErrorKind::NotFound => Ok(()),
                 _ => Err(err).context(error::DatastoreRemove { path: &path }),
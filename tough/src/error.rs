@@ -81,6 +81,30 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    /// A root metadata file lists more keys than the limit set by the consumer of this library.
+    #[snafu(display(
+        "root.json lists {} keys, more than the maximum of {}",
+        count,
+        max_keys
+    ))]
+    MaxKeysExceeded {
+        count: u64,
+        max_keys: u64,
+        backtrace: Backtrace,
+    },
+
+    /// A metadata file has more signatures than the limit set by the consumer of this library.
+    #[snafu(display(
+        "{} metadata has more than the maximum of {} signatures",
+        role,
+        max_signatures
+    ))]
+    MaxSignaturesExceeded {
+        role: RoleType,
+        max_signatures: u64,
+        backtrace: Backtrace,
+    },
+
     /// A file's maximum size exceeded a limit set by the consumer of this library or the metadata.
     #[snafu(display("Maximum size {} (specified by {}) exceeded", max_size, specifier))]
     MaxSizeExceeded {
@@ -89,6 +113,26 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    /// The size of snapshot.json listed in timestamp.json exceeds the limit set by the consumer of
+    /// this library.
+    #[snafu(display(
+        "timestamp.json lists a snapshot.json size of {}, more than the maximum of {}",
+        size,
+        max_snapshot_size
+    ))]
+    MaxSnapshotSizeExceeded {
+        size: u64,
+        max_snapshot_size: u64,
+        backtrace: Backtrace,
+    },
+
+    /// The targets metadata lists more targets than the limit set by the consumer of this library.
+    #[snafu(display("targets.json lists more than the maximum of {} targets", max_targets))]
+    MaxTargetsExceeded {
+        max_targets: u64,
+        backtrace: Backtrace,
+    },
+
     /// The maximum root updates setting was exceeded.
     #[snafu(display("Maximum root updates {} exceeded", max_root_updates))]
     MaxUpdatesExceeded {
@@ -85,9 +85,38 @@ impl<T: Read> Read for MaxSizeAdapter<T> {
     }
 }
 
+/// Counts the bytes read through it, passing the running total to `callback` after each read that
+/// returns data.
+pub(crate) struct ProgressAdapter<T, F> {
+    reader: T,
+    callback: F,
+    counter: u64,
+}
+
+impl<T, F: FnMut(u64)> ProgressAdapter<T, F> {
+    pub(crate) fn new(reader: T, callback: F) -> Self {
+        Self {
+            reader,
+            callback,
+            counter: 0,
+        }
+    }
+}
+
+impl<T: Read, F: FnMut(u64)> Read for ProgressAdapter<T, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.reader.read(buf)?;
+        if size > 0 {
+            self.counter += size as u64;
+            (self.callback)(self.counter);
+        }
+        Ok(size)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::io::{DigestAdapter, MaxSizeAdapter};
+    use crate::io::{DigestAdapter, MaxSizeAdapter, ProgressAdapter};
     use hex_literal::hex;
     use std::io::{Cursor, Read};
     use url::Url;
@@ -123,4 +152,14 @@ mod tests {
         let mut buf = Vec::new();
         assert!(reader.read_to_end(&mut buf).is_err());
     }
+
+    #[test]
+    fn test_progress_adapter() {
+        let mut progress = Vec::new();
+        let mut reader =
+            ProgressAdapter::new(Cursor::new(b"hello".to_vec()), |bytes| progress.push(bytes));
+        let mut buf = [0; 2];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(progress, [2, 4, 5]);
+    }
 }
@@ -19,22 +19,32 @@ mod datastore;
 pub mod error;
 mod fetch;
 mod io;
+mod limit;
+mod observer;
 pub mod schema;
 mod transport;
 
+pub use crate::observer::Observer;
 #[cfg(feature = "http")]
 pub use crate::transport::HttpTransport;
-pub use crate::transport::{FilesystemTransport, Transport};
+pub use crate::transport::{FilesystemTransport, SchemeTransport, SchemeTransportError, Transport};
 
 use crate::datastore::Datastore;
 use crate::error::Result;
 use crate::fetch::{fetch_max_size, fetch_sha256};
-use crate::schema::{Role, RoleType, Root, Signed, Snapshot, Timestamp};
+use crate::io::ProgressAdapter;
+use crate::limit::LimitedRole;
+use crate::observer::NullObserver;
+use crate::schema::{
+    Role, RoleType, Root, Signed, Snapshot, SnapshotMeta, Timestamp, TimestampMeta,
+};
 use chrono::{DateTime, Utc};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::io::Read;
+use std::num::NonZeroU64;
 use std::path::Path;
 use url::Url;
 
@@ -64,24 +74,39 @@ pub struct Settings<'a, R: Read> {
     ///
     /// This parameter implements [`Default`]; see its documentation for details.
     pub limits: Limits,
+
+    /// An [`Observer`] that is told about metadata fetches, root updates, and datastore writes
+    /// during [`Repository::load`], and about progress while reading targets from the loaded
+    /// repository.
+    pub observer: Option<&'a dyn Observer>,
 }
 
 /// Limits used when fetching repository metadata.
 ///
-/// These limits are implemented to prevent endless data attacks. Clients must ensure these values
-/// are set higher than what would reasonably be expected by a repository, but not so high that the
-/// amount of data could interfere with the system.
+/// These limits are implemented to prevent endless data attacks, and to bound the memory and CPU
+/// time spent on metadata regardless of what the repository serves. Clients must ensure these
+/// values are set higher than what would reasonably be expected by a repository, but not so high
+/// that the amount of data could interfere with the system.
 ///
 /// The [`Default`] implementation sets the following values:
 /// * `max_root_size`: 1 MiB
+/// * `max_snapshot_size`: 10 MiB
 /// * `max_targets_size`: 10 MiB
 /// * `max_timestamp_size`: 1 MiB
 /// * `max_root_updates`: 1024
+/// * `max_targets`: 1,000,000
+/// * `max_signatures`: 1024
+/// * `max_keys`: 1024
 #[derive(Debug, Clone)]
 pub struct Limits {
     /// The maximum allowable size in bytes for downloaded root.json files.
     pub max_root_size: u64,
 
+    /// The maximum allowable size in bytes for the downloaded snapshot.json file. The snapshot is
+    /// always downloaded up to the size listed in timestamp.json; if that size is larger than
+    /// this, the snapshot is not downloaded at all.
+    pub max_snapshot_size: u64,
+
     /// The maximum allowable size in bytes for downloaded targets.json file **if** the size is not
     /// listed in snapshots.json. This setting is ignored if the size of targets.json is in the
     /// signed snapshots.json file.
@@ -92,15 +117,30 @@ pub struct Limits {
 
     /// The maximum number of updates to root.json to download.
     pub max_root_updates: u64,
+
+    /// The maximum number of targets listed in targets.json. This is enforced while targets.json
+    /// is parsed, so no more than this many targets are held in memory.
+    pub max_targets: u64,
+
+    /// The maximum number of signatures on a metadata file. This is enforced while the file is
+    /// parsed, so metadata with more signatures is rejected before any of them are verified.
+    pub max_signatures: u64,
+
+    /// The maximum number of keys listed in a root.json file.
+    pub max_keys: u64,
 }
 
 impl Default for Limits {
     fn default() -> Self {
         Self {
-            max_root_size: 1024 * 1024,         // 1 MiB
-            max_targets_size: 1024 * 1024 * 10, // 10 MiB
-            max_timestamp_size: 1024 * 1024,    // 1 MiB
+            max_root_size: 1024 * 1024,          // 1 MiB
+            max_snapshot_size: 1024 * 1024 * 10, // 10 MiB
+            max_targets_size: 1024 * 1024 * 10,  // 10 MiB
+            max_timestamp_size: 1024 * 1024,     // 1 MiB
             max_root_updates: 1024,
+            max_targets: 1_000_000,
+            max_signatures: 1024,
+            max_keys: 1024,
         }
     }
 }
@@ -115,8 +155,14 @@ pub struct Repository<'a, T: Transport> {
     datastore: Datastore<'a>,
     earliest_expiration: DateTime<Utc>,
     earliest_expiration_role: RoleType,
+    root: Signed<Root>,
+    snapshot: Signed<Snapshot>,
+    timestamp: Signed<Timestamp>,
+    targets_version: NonZeroU64,
+    targets_expires: DateTime<Utc>,
     target_base_url: Url,
     targets: HashMap<String, Target>,
+    observer: &'a dyn Observer,
 }
 
 impl<'a, T: Transport> Repository<'a, T> {
@@ -144,15 +190,16 @@ impl<'a, T: Transport> Repository<'a, T> {
         let metadata_base_url = parse_url(settings.metadata_base_url)?;
         let target_base_url = parse_url(settings.target_base_url)?;
 
-        let datastore = Datastore::new(settings.datastore);
+        let observer = settings.observer.unwrap_or(&NullObserver);
+        let datastore = Datastore::new(settings.datastore, observer);
 
         // 0. Load the trusted root metadata file + 1. Update the root metadata file
         let root = load_root(
             transport,
             settings.root,
             &datastore,
-            settings.limits.max_root_size,
-            settings.limits.max_root_updates,
+            &settings.limits,
+            observer,
             &metadata_base_url,
         )?;
 
@@ -161,12 +208,21 @@ impl<'a, T: Transport> Repository<'a, T> {
             transport,
             &root,
             &datastore,
-            settings.limits.max_timestamp_size,
+            &settings.limits,
+            observer,
             &metadata_base_url,
         )?;
 
         // 3. Download the snapshot metadata file
-        let snapshot = load_snapshot(transport, &root, &timestamp, &datastore, &metadata_base_url)?;
+        let snapshot = load_snapshot(
+            transport,
+            &root,
+            &timestamp,
+            &datastore,
+            &settings.limits,
+            observer,
+            &metadata_base_url,
+        )?;
 
         // 4. Download the targets metadata file
         let targets = load_targets(
@@ -174,7 +230,8 @@ impl<'a, T: Transport> Repository<'a, T> {
             &root,
             &snapshot,
             &datastore,
-            settings.limits.max_targets_size,
+            &settings.limits,
+            observer,
             &metadata_base_url,
         )?;
 
@@ -193,6 +250,11 @@ impl<'a, T: Transport> Repository<'a, T> {
             datastore,
             earliest_expiration: earliest_expiration.to_owned(),
             earliest_expiration_role: *earliest_expiration_role,
+            targets_version: targets.signed.version,
+            targets_expires: targets.signed.expires,
+            root,
+            snapshot,
+            timestamp,
             target_base_url,
             targets: targets
                 .signed
@@ -200,6 +262,7 @@ impl<'a, T: Transport> Repository<'a, T> {
                 .into_iter()
                 .map(|(key, value)| (key, value.into()))
                 .collect(),
+            observer,
         })
     }
 
@@ -208,6 +271,31 @@ impl<'a, T: Transport> Repository<'a, T> {
         &self.targets
     }
 
+    /// Returns the most recent verified root metadata.
+    pub fn root(&self) -> &Signed<Root> {
+        &self.root
+    }
+
+    /// Returns the verified snapshot metadata.
+    pub fn snapshot(&self) -> &Signed<Snapshot> {
+        &self.snapshot
+    }
+
+    /// Returns the verified timestamp metadata.
+    pub fn timestamp(&self) -> &Signed<Timestamp> {
+        &self.timestamp
+    }
+
+    /// Returns the version of the verified targets metadata.
+    pub fn targets_version(&self) -> NonZeroU64 {
+        self.targets_version
+    }
+
+    /// Returns the expiration time of the verified targets metadata.
+    pub fn targets_expires(&self) -> DateTime<Utc> {
+        self.targets_expires
+    }
+
     /// Fetches a target from the repository.
     ///
     /// If the repository metadata is expired or there is an issue making the request, `Err` is
@@ -219,7 +307,9 @@ impl<'a, T: Transport> Repository<'a, T> {
     /// before its checksum is validated. If the maximum size is reached or there is a checksum
     /// mismatch, the reader returns a [`std::io::Error`]. **Consumers of this library must not use
     /// data from the reader if it returns an error.**
-    pub fn read_target(&self, name: &str) -> Result<Option<impl Read>> {
+    ///
+    /// The reader reports its progress to the [`Observer`] from [`Settings`], if there is one.
+    pub fn read_target(&self, name: &str) -> Result<Option<impl Read + 'a>> {
         // Check for repository metadata expiration.
         ensure!(
             system_time(&self.datastore)? < self.earliest_expiration,
@@ -252,7 +342,7 @@ impl<'a, T: Transport> Repository<'a, T> {
                 name.to_owned()
             };
 
-            Some(fetch_sha256(
+            let reader = fetch_sha256(
                 self.transport,
                 self.target_base_url.join(&file).context(error::JoinUrl {
                     path: file,
@@ -261,7 +351,11 @@ impl<'a, T: Transport> Repository<'a, T> {
                 target.length,
                 "targets.json",
                 &target.sha256,
-            )?)
+            )?;
+            let (observer, name, max_size) = (self.observer, name.to_owned(), target.length);
+            Some(ProgressAdapter::new(reader, move |bytes| {
+                observer.target_progress(&name, bytes, max_size);
+            }))
         } else {
             None
         })
@@ -292,27 +386,27 @@ impl From<crate::schema::Target> for Target {
 
 /// Ensures that system time has not stepped backward since it was last sampled
 fn system_time(datastore: &Datastore<'_>) -> Result<DateTime<Utc>> {
-    let file = "latest_known_time.json";
-    // Get 'current' system time
-    let sys_time = Utc::now();
-    // Load the latest known system time, if it exists
-    if let Some(Ok(latest_known_time)) = datastore
-        .reader(file)?
-        .map(serde_json::from_reader::<_, DateTime<Utc>>)
-    {
-        // Make sure the sampled system time did not go back in time
-        ensure!(
-            sys_time >= latest_known_time,
-            error::SystemTimeSteppedBackward {
-                sys_time,
-                latest_known_time
+    // Sample, check, and store the time while holding the datastore lock, so that concurrent
+    // callers (such as threads reading targets in parallel) can't store a later time between
+    // another caller's sample and its check.
+    datastore.update(
+        "latest_known_time.json",
+        |latest_known_time: Option<DateTime<Utc>>| {
+            // Get 'current' system time
+            let sys_time = Utc::now();
+            // Make sure the sampled system time did not go back in time
+            if let Some(latest_known_time) = latest_known_time {
+                ensure!(
+                    sys_time >= latest_known_time,
+                    error::SystemTimeSteppedBackward {
+                        sys_time,
+                        latest_known_time
+                    }
+                );
             }
-        );
-    }
-    // Store the latest known time
-    // Serializes RFC3339 time string and store to datastore
-    datastore.create(file, &sys_time)?;
-    Ok(sys_time)
+            Ok(sys_time)
+        },
+    )
 }
 
 fn check_expired<T: Role>(datastore: &Datastore<'_>, role: &T) -> Result<()> {
@@ -331,14 +425,93 @@ fn parse_url(url: &str) -> Result<Url> {
     Url::parse(&url).context(error::ParseUrl { url })
 }
 
+/// Checks that a root metadata file lists no more than `max_keys` keys. This is done before
+/// verifying it.
+fn check_root_limits(root: &Signed<Root>, limits: &Limits) -> Result<()> {
+    ensure!(
+        root.signed.keys.len() as u64 <= limits.max_keys,
+        error::MaxKeysExceeded {
+            count: root.signed.keys.len() as u64,
+            max_keys: limits.max_keys,
+        }
+    );
+    Ok(())
+}
+
+/// Fetches and parses a metadata file within the signature and target count `limits`, reporting
+/// the fetch to `observer`.
+fn fetch_metadata<M, R, F>(
+    observer: &dyn Observer,
+    role: RoleType,
+    url: &Url,
+    limits: &Limits,
+    fetch: F,
+) -> Result<Signed<M>>
+where
+    M: LimitedRole,
+    R: Read,
+    F: FnOnce(Url) -> Result<R>,
+{
+    observer.fetch_started(role, url);
+    read_metadata(observer, role, url, limits, fetch(url.clone()))
+}
+
+/// Parses a metadata file from the result of fetching it, after `observer` has been told the
+/// fetch started, and reports how the fetch ended.
+fn read_metadata<M, R>(
+    observer: &dyn Observer,
+    role: RoleType,
+    url: &Url,
+    limits: &Limits,
+    reader: Result<R>,
+) -> Result<Signed<M>>
+where
+    M: LimitedRole,
+    R: Read,
+{
+    let bytes = Cell::new(0);
+    let result = reader.and_then(|reader| {
+        limit::from_reader(
+            ProgressAdapter::new(reader, |total| bytes.set(total)),
+            limits,
+        )?
+        .context(error::ParseMetadata { role })
+    });
+    match &result {
+        Ok(_) => observer.fetch_finished(role, url, bytes.get()),
+        Err(err) => observer.fetch_failed(role, url, bytes.get(), err),
+    }
+    result
+}
+
+/// Reports `result` to `observer` as a rejection of the metadata for `role` if it is an error.
+fn reject<T>(observer: &dyn Observer, role: RoleType, result: Result<T>) -> Result<T> {
+    if let Err(err) = &result {
+        observer.role_rejected(role, err);
+    }
+    result
+}
+
+/// Reports the outcome of checking version `version` of the metadata for `role` to `observer`.
+fn report_checks(
+    observer: &dyn Observer,
+    role: RoleType,
+    version: NonZeroU64,
+    result: Result<()>,
+) -> Result<()> {
+    reject(observer, role, result)?;
+    observer.role_verified(role, version);
+    Ok(())
+}
+
 /// Steps 0 and 1 of the client application, which load the current root metadata file based on a
 /// trusted root metadata file.
 fn load_root<R: Read, T: Transport>(
     transport: &T,
     root: R,
     datastore: &Datastore<'_>,
-    max_root_size: u64,
-    max_root_updates: u64,
+    limits: &Limits,
+    observer: &dyn Observer,
     metadata_base_url: &Url,
 ) -> Result<Signed<Root>> {
     // 0. Load the trusted root metadata file. We assume that a good, trusted copy of this file was
@@ -346,7 +519,8 @@ fn load_root<R: Read, T: Transport>(
     //    that the expiration of the trusted root metadata file does not matter, because we will
     //    attempt to update it in the next step.
     let mut root: Signed<Root> =
-        serde_json::from_reader(root).context(error::ParseTrustedMetadata)?;
+        limit::from_reader(root, limits)?.context(error::ParseTrustedMetadata)?;
+    check_root_limits(&root, limits)?;
     root.signed
         .verify_role(&root)
         .context(error::VerifyTrustedMetadata)?;
@@ -383,90 +557,66 @@ fn load_root<R: Read, T: Transport>(
         //   step 1.8. The value for Y is set by the authors of the application using TUF. For
         //   example, Y may be 2^10.
         ensure!(
-            root.signed.version.get() < original_root_version + max_root_updates,
-            error::MaxUpdatesExceeded { max_root_updates }
+            root.signed.version.get() < original_root_version + limits.max_root_updates,
+            error::MaxUpdatesExceeded {
+                max_root_updates: limits.max_root_updates
+            }
         );
         let path = format!("{}.root.json", root.signed.version.get() + 1);
-        match fetch_max_size(
+        let url = metadata_base_url.join(&path).context(error::JoinUrl {
+            path,
+            url: metadata_base_url.to_owned(),
+        })?;
+        observer.fetch_started(RoleType::Root, &url);
+        let reader = match fetch_max_size(
             transport,
-            metadata_base_url.join(&path).context(error::JoinUrl {
-                path,
-                url: metadata_base_url.to_owned(),
-            })?,
-            max_root_size,
+            url.clone(),
+            limits.max_root_size,
             "max_root_size argument",
         ) {
-            Err(_) => break, // If this file is not available, then go to step 1.8.
-            Ok(reader) => {
-                let new_root: Signed<Root> =
-                    serde_json::from_reader(reader).context(error::ParseMetadata {
-                        role: RoleType::Root,
-                    })?;
-
-                // 1.3. Check signatures. Version N+1 of the root metadata file MUST have been
-                //   signed by: (1) a threshold of keys specified in the trusted root metadata file
-                //   (version N), and (2) a threshold of keys specified in the new root metadata
-                //   file being validated (version N+1). If version N+1 is not signed as required,
-                //   discard it, abort the update cycle, and report the signature failure. On the
-                //   next update cycle, begin at step 0 and version N of the root metadata file.
-                root.signed
-                    .verify_role(&new_root)
-                    .context(error::VerifyMetadata {
-                        role: RoleType::Root,
-                    })?;
-                new_root
-                    .signed
-                    .verify_role(&new_root)
-                    .context(error::VerifyMetadata {
-                        role: RoleType::Root,
-                    })?;
-
-                // 1.4. Check for a rollback attack. The version number of the trusted root
-                //   metadata file (version N) must be less than or equal to the version number of
-                //   the new root metadata file (version N+1). Effectively, this means checking
-                //   that the version number signed in the new root metadata file is indeed N+1. If
-                //   the version of the new root metadata file is less than the trusted metadata
-                //   file, discard it, abort the update cycle, and report the rollback attack. On
-                //   the next update cycle, begin at step 0 and version N of the root metadata
-                //   file.
-                ensure!(
-                    root.signed.version <= new_root.signed.version,
-                    error::OlderMetadata {
-                        role: RoleType::Root,
-                        current_version: root.signed.version,
-                        new_version: new_root.signed.version
-                    }
-                );
+            // If this file is not available, then go to step 1.8. This is the expected end of the
+            // root chain, so it is not reported to the observer as a failure.
+            Err(error::Error::Transport { .. }) => break,
+            reader => reader,
+        };
+        let new_root: Signed<Root> = read_metadata(observer, RoleType::Root, &url, limits, reader)?;
+        reject(
+            observer,
+            RoleType::Root,
+            check_root_update(&root, &new_root, limits),
+        )?;
 
-                // Off-spec: 1.4 specifies that the version number of the trusted root metadata
-                // file must be less than or equal to the version number of the new root metadata
-                // file. If they are equal, this will create an infinite loop, so we ignore the new
-                // root metadata file but do not report an error. This could only happen if the
-                // path we built above, referencing N+1, has a filename that doesn't match its
-                // contents, which would have to list version N.
-                if root.signed.version == new_root.signed.version {
-                    break;
-                }
+        // Off-spec: 1.4 specifies that the version number of the trusted root metadata file must be
+        // less than or equal to the version number of the new root metadata file. If they are
+        // equal, this will create an infinite loop, so we ignore the new root metadata file but do
+        // not report an error. This could only happen if the path we built above, referencing N+1,
+        // has a filename that doesn't match its contents, which would have to list version N.
+        if root.signed.version == new_root.signed.version {
+            break;
+        }
 
-                // 1.5. Note that the expiration of the new (intermediate) root metadata file does
-                //   not matter yet, because we will check for it in step 1.8.
-                //
-                // 1.6. Set the trusted root metadata file to the new root metadata file.
-                //
-                // (This is where version N+1 becomes version N.)
-                root = new_root;
+        // 1.5. Note that the expiration of the new (intermediate) root metadata file does not
+        //   matter yet, because we will check for it in step 1.8.
+        //
+        // 1.6. Set the trusted root metadata file to the new root metadata file.
+        //
+        // (This is where version N+1 becomes version N.)
+        observer.root_updated(root.signed.version, new_root.signed.version);
+        root = new_root;
 
-                // 1.7. Repeat steps 1.1 to 1.7.
-                continue;
-            }
-        }
+        // 1.7. Repeat steps 1.1 to 1.7.
     }
 
     // 1.8. Check for a freeze attack. The latest known time should be lower than the expiration
     //   timestamp in the trusted root metadata file (version N). If the trusted root metadata file
     //   has expired, abort the update cycle, report the potential freeze attack. On the next
     //   update cycle, begin at step 0 and version N of the root metadata file.
-    check_expired(datastore, &root.signed)?;
+    report_checks(
+        observer,
+        RoleType::Root,
+        root.signed.version,
+        check_expired(datastore, &root.signed),
+    )?;
 
     // 1.9. If the timestamp and / or snapshot keys have been rotated, then delete the trusted
     //   timestamp and snapshot metadata files. This is done in order to recover from fast-forward
@@ -495,12 +645,53 @@ fn load_root<R: Read, T: Transport>(
     Ok(root)
 }
 
+/// Checks version N+1 of the root metadata file against the trusted version N, as part of step 1.
+fn check_root_update(root: &Signed<Root>, new_root: &Signed<Root>, limits: &Limits) -> Result<()> {
+    check_root_limits(new_root, limits)?;
+
+    // 1.3. Check signatures. Version N+1 of the root metadata file MUST have been signed by: (1) a
+    //   threshold of keys specified in the trusted root metadata file (version N), and (2) a
+    //   threshold of keys specified in the new root metadata file being validated (version N+1). If
+    //   version N+1 is not signed as required, discard it, abort the update cycle, and report the
+    //   signature failure. On the next update cycle, begin at step 0 and version N of the root
+    //   metadata file.
+    root.signed
+        .verify_role(new_root)
+        .context(error::VerifyMetadata {
+            role: RoleType::Root,
+        })?;
+    new_root
+        .signed
+        .verify_role(new_root)
+        .context(error::VerifyMetadata {
+            role: RoleType::Root,
+        })?;
+
+    // 1.4. Check for a rollback attack. The version number of the trusted root metadata file
+    //   (version N) must be less than or equal to the version number of the new root metadata file
+    //   (version N+1). Effectively, this means checking that the version number signed in the new
+    //   root metadata file is indeed N+1. If the version of the new root metadata file is less than
+    //   the trusted metadata file, discard it, abort the update cycle, and report the rollback
+    //   attack. On the next update cycle, begin at step 0 and version N of the root metadata file.
+    ensure!(
+        root.signed.version <= new_root.signed.version,
+        error::OlderMetadata {
+            role: RoleType::Root,
+            current_version: root.signed.version,
+            new_version: new_root.signed.version
+        }
+    );
+
+    Ok(())
+}
+
 /// Step 2 of the client application, which loads the timestamp metadata file.
 fn load_timestamp<T: Transport>(
     transport: &T,
     root: &Signed<Root>,
     datastore: &Datastore<'_>,
-    max_timestamp_size: u64,
+    limits: &Limits,
+    observer: &dyn Observer,
     metadata_base_url: &Url,
 ) -> Result<Signed<Timestamp>> {
     // 2. Download the timestamp metadata file, up to Y number of bytes (because the size is
@@ -508,25 +699,44 @@ fn load_timestamp<T: Transport>(
     //    example, Y may be tens of kilobytes. The filename used to download the timestamp metadata
     //    file is of the fixed form FILENAME.EXT (e.g., timestamp.json).
     let path = "timestamp.json";
-    let reader = fetch_max_size(
-        transport,
-        metadata_base_url.join(path).context(error::JoinUrl {
-            path,
-            url: metadata_base_url.to_owned(),
-        })?,
-        max_timestamp_size,
-        "max_timestamp_size argument",
-    )?;
+    let url = metadata_base_url.join(path).context(error::JoinUrl {
+        path,
+        url: metadata_base_url.to_owned(),
+    })?;
     let timestamp: Signed<Timestamp> =
-        serde_json::from_reader(reader).context(error::ParseMetadata {
-            role: RoleType::Timestamp,
+        fetch_metadata(observer, RoleType::Timestamp, &url, limits, |url| {
+            fetch_max_size(
+                transport,
+                url,
+                limits.max_timestamp_size,
+                "max_timestamp_size argument",
+            )
         })?;
 
+    report_checks(
+        observer,
+        RoleType::Timestamp,
+        timestamp.signed.version,
+        check_timestamp(root, datastore, &timestamp),
+    )?;
+
+    // Now that everything seems okay, write the timestamp file to the datastore.
+    datastore.create("timestamp.json", &timestamp)?;
+
+    Ok(timestamp)
+}
+
+/// Checks a new timestamp metadata file, as part of step 2.
+fn check_timestamp(
+    root: &Signed<Root>,
+    datastore: &Datastore<'_>,
+    timestamp: &Signed<Timestamp>,
+) -> Result<()> {
     // 2.1. Check signatures. The new timestamp metadata file must have been signed by a threshold
     //   of keys specified in the trusted root metadata file. If the new timestamp metadata file is
     //   not properly signed, discard it, abort the update cycle, and report the signature failure.
     root.signed
-        .verify_role(&timestamp)
+        .verify_role(timestamp)
         .context(error::VerifyMetadata {
             role: RoleType::Timestamp,
         })?;
@@ -557,10 +767,7 @@ fn load_timestamp<T: Transport>(
     //   expired, discard it, abort the update cycle, and report the potential freeze attack.
     check_expired(datastore, &timestamp.signed)?;
 
-    // Now that everything seems okay, write the timestamp file to the datastore.
-    datastore.create("timestamp.json", &timestamp)?;
-
-    Ok(timestamp)
+    Ok(())
 }
 
 /// Step 3 of the client application, which loads the snapshot metadata file.
@@ -569,6 +776,8 @@ fn load_snapshot<T: Transport>(
     root: &Signed<Root>,
     timestamp: &Signed<Timestamp>,
     datastore: &Datastore<'_>,
+    limits: &Limits,
+    observer: &dyn Observer,
     metadata_base_url: &Url,
 ) -> Result<Signed<Snapshot>> {
     // 3. Download snapshot metadata file, up to the number of bytes specified in the timestamp
@@ -586,26 +795,58 @@ fn load_snapshot<T: Transport>(
             file: "snapshot.json",
             role: RoleType::Timestamp,
         })?;
+    if snapshot_meta.length > limits.max_snapshot_size {
+        return reject(
+            observer,
+            RoleType::Snapshot,
+            error::MaxSnapshotSizeExceeded {
+                size: snapshot_meta.length,
+                max_snapshot_size: limits.max_snapshot_size,
+            }
+            .fail(),
+        );
+    }
     let path = if root.signed.consistent_snapshot {
         format!("{}.snapshot.json", snapshot_meta.version)
     } else {
         "snapshot.json".to_owned()
     };
-    let reader = fetch_sha256(
-        transport,
-        metadata_base_url.join(&path).context(error::JoinUrl {
-            path,
-            url: metadata_base_url.to_owned(),
-        })?,
-        snapshot_meta.length,
-        "timestamp.json",
-        &snapshot_meta.hashes.sha256,
-    )?;
+    let url = metadata_base_url.join(&path).context(error::JoinUrl {
+        path,
+        url: metadata_base_url.to_owned(),
+    })?;
     let snapshot: Signed<Snapshot> =
-        serde_json::from_reader(reader).context(error::ParseMetadata {
-            role: RoleType::Snapshot,
+        fetch_metadata(observer, RoleType::Snapshot, &url, limits, |url| {
+            fetch_sha256(
+                transport,
+                url,
+                snapshot_meta.length,
+                "timestamp.json",
+                &snapshot_meta.hashes.sha256,
+            )
         })?;
 
+    report_checks(
+        observer,
+        RoleType::Snapshot,
+        snapshot.signed.version,
+        check_snapshot(root, datastore, &snapshot, snapshot_meta),
+    )?;
+
+    // Now that everything seems okay, write the timestamp file to the datastore.
+    datastore.create("snapshot.json", &snapshot)?;
+
+    Ok(snapshot)
+}
+
+/// Checks a new snapshot metadata file against the timestamp metadata that lists it, as part of
+/// step 3.
+fn check_snapshot(
+    root: &Signed<Root>,
+    datastore: &Datastore<'_>,
+    snapshot: &Signed<Snapshot>,
+    snapshot_meta: &TimestampMeta,
+) -> Result<()> {
     // 3.1. Check against timestamp metadata. The hashes and version number of the new snapshot
     //   metadata file MUST match the hashes and version number listed in timestamp metadata. If
     //   hashes and version do not match, discard the new snapshot metadata, abort the update
@@ -626,7 +867,7 @@ fn load_snapshot<T: Transport>(
     //   not signed as required, discard it, abort the update cycle, and report the signature
     //   failure.
     root.signed
-        .verify_role(&snapshot)
+        .verify_role(snapshot)
         .context(error::VerifyMetadata {
             role: RoleType::Snapshot,
         })?;
@@ -688,10 +929,7 @@ fn load_snapshot<T: Transport>(
     //   it, abort the update cycle, and report the potential freeze attack.
     check_expired(datastore, &snapshot.signed)?;
 
-    // Now that everything seems okay, write the timestamp file to the datastore.
-    datastore.create("snapshot.json", &snapshot)?;
-
-    Ok(snapshot)
+    Ok(())
 }
 
 /// Step 4 of the client application, which loads the targets metadata file.
@@ -700,7 +938,8 @@ fn load_targets<T: Transport>(
     root: &Signed<Root>,
     snapshot: &Signed<Snapshot>,
     datastore: &Datastore<'_>,
-    max_targets_size: u64,
+    limits: &Limits,
+    observer: &dyn Observer,
     metadata_base_url: &Url,
 ) -> Result<Signed<crate::schema::Targets>> {
     // 4. Download the top-level targets metadata file, up to either the number of bytes specified
@@ -731,29 +970,50 @@ fn load_targets<T: Transport>(
     })?;
     let (max_targets_size, specifier) = match targets_meta.length {
         Some(length) => (length, "snapshot.json"),
-        None => (max_targets_size, "max_targets_size parameter"),
-    };
-    let reader = if let Some(hashes) = &targets_meta.hashes {
-        Box::new(fetch_sha256(
-            transport,
-            targets_url,
-            max_targets_size,
-            specifier,
-            &hashes.sha256,
-        )?) as Box<dyn Read>
-    } else {
-        Box::new(fetch_max_size(
-            transport,
-            targets_url,
-            max_targets_size,
-            specifier,
-        )?)
+        None => (limits.max_targets_size, "max_targets_size parameter"),
     };
     let targets: Signed<crate::schema::Targets> =
-        serde_json::from_reader(reader).context(error::ParseMetadata {
-            role: RoleType::Targets,
+        fetch_metadata(observer, RoleType::Targets, &targets_url, limits, |url| {
+            Ok(if let Some(hashes) = &targets_meta.hashes {
+                Box::new(fetch_sha256(
+                    transport,
+                    url,
+                    max_targets_size,
+                    specifier,
+                    &hashes.sha256,
+                )?) as Box<dyn Read>
+            } else {
+                Box::new(fetch_max_size(transport, url, max_targets_size, specifier)?)
+            })
         })?;
 
+    report_checks(
+        observer,
+        RoleType::Targets,
+        targets.signed.version,
+        check_targets(root, datastore, &targets, targets_meta),
+    )?;
+
+    // 4.5. Perform a preorder depth-first search for metadata about the desired target, beginning
+    //   with the top-level targets role.
+    //
+    // (This library does not yet handle delegated roles, so we just use the parsed targets from
+    // targets.json.)
+
+    // Now that everything seems okay, write the timestamp file to the datastore.
+    datastore.create("targets.json", &targets)?;
+
+    Ok(targets)
+}
+
+/// Checks a new targets metadata file against the snapshot metadata that lists it, as part of
+/// step 4.
+fn check_targets(
+    root: &Signed<Root>,
+    datastore: &Datastore<'_>,
+    targets: &Signed<crate::schema::Targets>,
+    targets_meta: &SnapshotMeta,
+) -> Result<()> {
     // 4.1. Check against snapshot metadata. The hashes (if any), and version number of the new
     //   targets metadata file MUST match the trusted snapshot metadata. This is done, in part, to
     //   prevent a mix-and-match attack by man-in-the-middle attackers. If the new targets metadata
@@ -774,7 +1034,7 @@ fn load_targets<T: Transport>(
     //   targets metadata file is not signed as required, discard it, abort the update cycle, and
     //   report the failure.
     root.signed
-        .verify_role(&targets)
+        .verify_role(targets)
         .context(error::VerifyMetadata {
             role: RoleType::Targets,
         })?;
@@ -805,16 +1065,7 @@ fn load_targets<T: Transport>(
     //   it, abort the update cycle, and report the potential freeze attack.
     check_expired(datastore, &targets.signed)?;
 
-    // 4.5. Perform a preorder depth-first search for metadata about the desired target, beginning
-    //   with the top-level targets role.
-    //
-    // (This library does not yet handle delegated roles, so we just use the parsed targets from
-    // targets.json.)
-
-    // Now that everything seems okay, write the timestamp file to the datastore.
-    datastore.create("targets.json", &targets)?;
-
-    Ok(targets)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -0,0 +1,352 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Deserialization of signed metadata that enforces the signature and target count [`Limits`] as
+//! it reads, so that a repository cannot make us hold more in memory than those limits allow.
+
+use crate::error::{self, Error, Result};
+use crate::schema::{
+    Role, RoleType, Root, Signature, Signed, Snapshot, Target, Targets, Timestamp,
+};
+use crate::Limits;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Unexpected, Visitor,
+};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+
+/// Deserializes signed metadata from `reader`. If a limit is exceeded, that error is returned;
+/// otherwise the result of parsing is returned for the caller to add context to.
+pub(crate) fn from_reader<T, R>(reader: R, limits: &Limits) -> Result<serde_json::Result<Signed<T>>>
+where
+    T: LimitedRole,
+    R: Read,
+{
+    let exceeded = Cell::new(None);
+    let limiter = Limiter {
+        limits,
+        exceeded: &exceeded,
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let result = SignedSeed(limiter, PhantomData)
+        .deserialize(&mut deserializer)
+        .and_then(|signed| deserializer.end().map(|()| signed));
+    match exceeded.take() {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+/// The limits to enforce, and a place to keep the error for the first one exceeded; serde can
+/// only carry a message through its own error type.
+#[derive(Clone, Copy)]
+pub(crate) struct Limiter<'a> {
+    limits: &'a Limits,
+    exceeded: &'a Cell<Option<Error>>,
+}
+
+impl Limiter<'_> {
+    fn fail<T, E: de::Error>(self, result: Result<T>) -> std::result::Result<T, E> {
+        result.map_err(|err| {
+            let message = E::custom(&err);
+            self.exceeded.set(Some(err));
+            message
+        })
+    }
+}
+
+/// A role that can be deserialized within the [`Limits`] of a [`Limiter`].
+pub(crate) trait LimitedRole: Role + Sized {
+    fn deserialize_limited<'de, D: Deserializer<'de>>(
+        deserializer: D,
+        limiter: Limiter<'_>,
+    ) -> std::result::Result<Self, D::Error>;
+}
+
+macro_rules! unlimited_role {
+    ($($role:ty),*) => {
+        $(
+            impl LimitedRole for $role {
+                fn deserialize_limited<'de, D: Deserializer<'de>>(
+                    deserializer: D,
+                    _limiter: Limiter<'_>,
+                ) -> std::result::Result<Self, D::Error> {
+                    Self::deserialize(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+unlimited_role!(Root, Snapshot, Timestamp);
+
+impl LimitedRole for Targets {
+    fn deserialize_limited<'de, D: Deserializer<'de>>(
+        deserializer: D,
+        limiter: Limiter<'_>,
+    ) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_map(TargetsVisitor(limiter))
+    }
+}
+
+/// Sets a field that must appear at most once.
+fn set_once<T, E: de::Error>(
+    field: &mut Option<T>,
+    name: &'static str,
+    value: T,
+) -> std::result::Result<(), E> {
+    if field.is_some() {
+        return Err(E::duplicate_field(name));
+    }
+    *field = Some(value);
+    Ok(())
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+struct SignedSeed<'a, T>(Limiter<'a>, PhantomData<T>);
+
+impl<'de, T: LimitedRole> DeserializeSeed<'de> for SignedSeed<'_, T> {
+    type Value = Signed<T>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, T: LimitedRole> Visitor<'de> for SignedSeed<'_, T> {
+    type Value = Signed<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("signed metadata")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(
+        self,
+        mut map: M,
+    ) -> std::result::Result<Self::Value, M::Error> {
+        let mut signed = None;
+        let mut signatures = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "signed" => set_once(
+                    &mut signed,
+                    "signed",
+                    map.next_value_seed(RoleSeed(self.0, PhantomData))?,
+                )?,
+                "signatures" => set_once(
+                    &mut signatures,
+                    "signatures",
+                    map.next_value_seed(SignaturesSeed(self.0, T::TYPE))?,
+                )?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Signed {
+            signed: signed.ok_or_else(|| de::Error::missing_field("signed"))?,
+            signatures: signatures.ok_or_else(|| de::Error::missing_field("signatures"))?,
+        })
+    }
+}
+
+struct RoleSeed<'a, T>(Limiter<'a>, PhantomData<T>);
+
+impl<'de, T: LimitedRole> DeserializeSeed<'de> for RoleSeed<'_, T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        T::deserialize_limited(deserializer, self.0)
+    }
+}
+
+/// Reads the signatures of a role, failing as soon as there are more than `max_signatures`.
+struct SignaturesSeed<'a>(Limiter<'a>, RoleType);
+
+impl<'de> DeserializeSeed<'de> for SignaturesSeed<'_> {
+    type Value = Vec<Signature>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SignaturesSeed<'_> {
+    type Value = Vec<Signature>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a list of signatures")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(
+        self,
+        mut seq: S,
+    ) -> std::result::Result<Self::Value, S::Error> {
+        let max_signatures = self.0.limits.max_signatures;
+        let mut signatures = Vec::new();
+        while let Some(signature) = seq.next_element()? {
+            if signatures.len() as u64 == max_signatures {
+                return self.0.fail(
+                    error::MaxSignaturesExceeded {
+                        role: self.1,
+                        max_signatures,
+                    }
+                    .fail(),
+                );
+            }
+            signatures.push(signature);
+        }
+        Ok(signatures)
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// Reads targets metadata the way its derived `Deserialize` does, except for the `targets` map.
+struct TargetsVisitor<'a>(Limiter<'a>);
+
+impl<'de> Visitor<'de> for TargetsVisitor<'_> {
+    type Value = Targets;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("targets metadata")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(
+        self,
+        mut map: M,
+    ) -> std::result::Result<Self::Value, M::Error> {
+        let mut type_ = None;
+        let mut spec_version = None;
+        let mut version = None;
+        let mut expires = None;
+        let mut targets = None;
+        let mut extra = HashMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "_type" => set_once(&mut type_, "_type", map.next_value::<String>()?)?,
+                "spec_version" => set_once(&mut spec_version, "spec_version", map.next_value()?)?,
+                "version" => set_once(&mut version, "version", map.next_value()?)?,
+                "expires" => set_once(&mut expires, "expires", map.next_value()?)?,
+                "targets" => set_once(
+                    &mut targets,
+                    "targets",
+                    map.next_value_seed(TargetsSeed(self.0))?,
+                )?,
+                _ => {
+                    let value = map.next_value()?;
+                    extra.insert(key, value);
+                }
+            }
+        }
+        match type_ {
+            Some(ref type_) if type_ == "targets" => {}
+            Some(type_) => {
+                return Err(de::Error::invalid_value(
+                    Unexpected::Str(&type_),
+                    &"targets",
+                ))
+            }
+            None => return Err(de::Error::missing_field("_type")),
+        }
+        Ok(Targets {
+            spec_version: spec_version.ok_or_else(|| de::Error::missing_field("spec_version"))?,
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            expires: expires.ok_or_else(|| de::Error::missing_field("expires"))?,
+            targets: targets.ok_or_else(|| de::Error::missing_field("targets"))?,
+            _extra: extra,
+        })
+    }
+}
+
+/// Reads the `targets` map, failing as soon as there are more than `max_targets` entries.
+struct TargetsSeed<'a>(Limiter<'a>);
+
+impl<'de> DeserializeSeed<'de> for TargetsSeed<'_> {
+    type Value = HashMap<String, Target>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TargetsSeed<'_> {
+    type Value = HashMap<String, Target>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map of targets")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(
+        self,
+        mut map: M,
+    ) -> std::result::Result<Self::Value, M::Error> {
+        let max_targets = self.0.limits.max_targets;
+        let mut targets = HashMap::new();
+        while let Some((name, target)) = map.next_entry()? {
+            if targets.len() as u64 == max_targets {
+                return self
+                    .0
+                    .fail(error::MaxTargetsExceeded { max_targets }.fail());
+            }
+            targets.insert(name, target);
+        }
+        Ok(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_reader;
+    use crate::error::Error;
+    use crate::schema::{RoleType, Signed, Targets, Timestamp};
+    use crate::Limits;
+
+    #[test]
+    fn targets_match_derived() {
+        let json = include_str!("../tests/data/tuf-reference-impl/metadata/targets.json");
+        let derived: Signed<Targets> = serde_json::from_str(json).unwrap();
+        let limited: Signed<Targets> = from_reader(json.as_bytes(), &Limits::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(derived, limited);
+    }
+
+    #[test]
+    fn signatures_limited_while_reading() {
+        // This file is cut off partway through its signatures; the limit must be hit before that.
+        let json = format!(
+            r#"{{"signatures":[{}"#,
+            r#"{"keyid":"00","sig":"00"},"#.repeat(1000)
+        );
+        let limits = Limits {
+            max_signatures: 2,
+            ..Limits::default()
+        };
+        match from_reader::<Timestamp, _>(json.as_bytes(), &limits) {
+            Err(Error::MaxSignaturesExceeded {
+                role: RoleType::Timestamp,
+                max_signatures: 2,
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
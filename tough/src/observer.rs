@@ -0,0 +1,66 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::error::Error;
+use crate::schema::RoleType;
+use std::fmt;
+use std::num::NonZeroU64;
+use url::Url;
+
+/// Receives events while [`Repository::load`] fetches and verifies metadata, and while the readers
+/// returned by [`Repository::read_target`] are read.
+///
+/// All methods do nothing by default, so implementations only need to handle the events they care
+/// about. Events are delivered synchronously on the thread doing the work, so methods should
+/// return quickly.
+///
+/// [`Repository::load`]: struct.Repository.html#method.load
+/// [`Repository::read_target`]: struct.Repository.html#method.read_target
+pub trait Observer: Send + Sync {
+    /// Called before a metadata file is fetched.
+    fn fetch_started(&self, _role: RoleType, _url: &Url) {}
+
+    /// Called after a metadata file is fetched and parsed, with the number of bytes read.
+    fn fetch_finished(&self, _role: RoleType, _url: &Url, _bytes: u64) {}
+
+    /// Called when fetching or parsing a metadata file fails, with the number of bytes read before
+    /// the failure.
+    ///
+    /// While updating the root metadata, tough fetches root.json versions until one is not
+    /// available. That last attempt only marks the end of the chain, so it is reported as started
+    /// but not as failed.
+    fn fetch_failed(&self, _role: RoleType, _url: &Url, _bytes: u64, _error: &Error) {}
+
+    /// Called when a newer root.json is verified and becomes the trusted root metadata.
+    fn root_updated(&self, _old_version: NonZeroU64, _new_version: NonZeroU64) {}
+
+    /// Called when the metadata for a role passes all of its checks and is trusted for the rest
+    /// of the load, with its version.
+    fn role_verified(&self, _role: RoleType, _version: NonZeroU64) {}
+
+    /// Called when the metadata for a role fails one of the checks made besides fetching and
+    /// parsing it, such as for signatures, versions, expiration or limits. The load fails with
+    /// `error`.
+    fn role_rejected(&self, _role: RoleType, _error: &Error) {}
+
+    /// Called after a file is written to the datastore.
+    fn datastore_written(&self, _file: &str) {}
+
+    /// Called after a file is removed from the datastore.
+    fn datastore_removed(&self, _file: &str) {}
+
+    /// Called each time data is read from a target, with the total number of bytes read so far
+    /// and the maximum size of the target listed in targets.json.
+    fn target_progress(&self, _name: &str, _bytes: u64, _max_size: u64) {}
+}
+
+impl fmt::Debug for dyn Observer + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// The observer used when `Settings::observer` is `None`.
+pub(crate) struct NullObserver;
+
+impl Observer for NullObserver {}
@@ -77,6 +77,7 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::schema::key::Key;
     use crate::schema::{Root, Signed};
 
     #[test]
@@ -86,4 +87,19 @@ mod tests {
         ))
         .is_err());
     }
+
+    #[test]
+    fn malformed_known_key() {
+        // Keys with a recognized type and scheme must still parse; only unrecognized ones are
+        // kept as `Key::Unknown`.
+        assert!(serde_json::from_str::<Key>(
+            r#"{"keytype":"ed25519","scheme":"ed25519","keyval":{"public":"zz"}}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Key>(r#"{"keytype":"rsa","keyval":{}}"#).is_err());
+        assert!(matches!(
+            serde_json::from_str::<Key>(r#"{"keytype":"rsa","scheme":"rsa-x","keyval":{}}"#),
+            Ok(Key::Unknown { .. })
+        ));
+    }
 }
@@ -1,5 +1,5 @@
 use crate::schema::error::{self, Error};
-use crate::schema::spki;
+use crate::schema::spki::{self, Parameters};
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use snafu::ResultExt;
 use std::cmp::Ordering;
@@ -90,17 +90,18 @@ pub struct RsaPem;
 
 impl Decode for RsaPem {
     fn decode(s: &str) -> Result<Vec<u8>, Error> {
-        spki::decode(spki::OID_RSA_ENCRYPTION, None, s)
+        spki::decode(spki::OID_RSA_ENCRYPTION, Parameters::Null, s)
     }
 }
 
 impl Encode for RsaPem {
     fn encode(b: &[u8]) -> String {
-        spki::encode(spki::OID_RSA_ENCRYPTION, None, b)
+        spki::encode(spki::OID_RSA_ENCRYPTION, Parameters::Null, b)
     }
 }
 
-/// [`Decode`]/[`Encode`] implementation for PEM-encoded ECDSA public keys.
+/// [`Decode`]/[`Encode`] implementation for PEM-encoded ECDSA public keys on the P-256 or P-384
+/// curves.
 #[derive(Debug, Clone)]
 pub struct EcdsaPem;
 
@@ -108,9 +109,16 @@ impl Decode for EcdsaPem {
     fn decode(s: &str) -> Result<Vec<u8>, Error> {
         spki::decode(
             spki::OID_EC_PUBLIC_KEY,
-            Some(spki::OID_EC_PARAM_SECP256R1),
+            Parameters::Oid(spki::OID_EC_PARAM_SECP256R1),
             s,
         )
+        .or_else(|_| {
+            spki::decode(
+                spki::OID_EC_PUBLIC_KEY,
+                Parameters::Oid(spki::OID_EC_PARAM_SECP384R1),
+                s,
+            )
+        })
     }
 }
 
@@ -118,12 +126,18 @@ impl Encode for EcdsaPem {
     fn encode(b: &[u8]) -> String {
         spki::encode(
             spki::OID_EC_PUBLIC_KEY,
-            Some(spki::OID_EC_PARAM_SECP256R1),
+            Parameters::Oid(spki::ecdsa_curve(b)),
             b,
         )
     }
 }
 
+/// The length of an uncompressed P-256 public key.
+pub(crate) const ECDSA_P256_PUBLIC_KEY_LEN: usize = 1 + 2 * 32;
+
+/// The length of an uncompressed P-384 public key.
+pub(crate) const ECDSA_P384_PUBLIC_KEY_LEN: usize = 1 + 2 * 48;
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 impl<'de, T: Decode> Deserialize<'de> for Decoded<T> {
@@ -69,6 +69,10 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    /// Failed to find the `SubjectPublicKeyInfo` document in an X.509 certificate.
+    #[snafu(display("Invalid X.509 certificate"))]
+    CertificateDecode { backtrace: Backtrace },
+
     /// Failed to extract a bit string from a `SubjectPublicKeyInfo` document.
     #[snafu(display("Invalid SubjectPublicKeyInfo document"))]
     SpkiDecode { backtrace: Backtrace },
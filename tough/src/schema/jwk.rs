@@ -0,0 +1,97 @@
+//! Functions for reading and writing JSON Web Keys ([RFC 7517]). The key parameters for RSA and
+//! elliptic curve keys are defined in [RFC 7518], and those for Ed25519 in [RFC 8037].
+//!
+//! [RFC 7517]: https://tools.ietf.org/html/rfc7517
+//! [RFC 7518]: https://tools.ietf.org/html/rfc7518#section-6
+//! [RFC 8037]: https://tools.ietf.org/html/rfc8037#section-2
+
+use super::decoded::{ECDSA_P256_PUBLIC_KEY_LEN, ECDSA_P384_PUBLIC_KEY_LEN};
+use super::key::Key;
+use super::spki;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The parameters of a public JWK that we use. Anything else, such as `kid` or `use`, is ignored.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kty")]
+enum Jwk {
+    #[serde(rename = "RSA")]
+    Rsa { n: String, e: String },
+    #[serde(rename = "EC")]
+    Ec { crv: String, x: String, y: String },
+    #[serde(rename = "OKP")]
+    Okp { crv: String, x: String },
+}
+
+/// Parses a JWK. Returns `None` if it isn't one or uses an unsupported key type or curve.
+pub(super) fn decode(s: &str) -> Option<Key> {
+    Some(match serde_json::from_str(s).ok()? {
+        Jwk::Rsa { n, e } => {
+            Key::rsa(spki::encode_rsa_public_key(&base64url(&n)?, &base64url(&e)?).into())
+        }
+        Jwk::Ec { crv, x, y } => {
+            let len = match crv.as_str() {
+                "P-256" => ECDSA_P256_PUBLIC_KEY_LEN,
+                "P-384" => ECDSA_P384_PUBLIC_KEY_LEN,
+                _ => return None,
+            };
+            // An uncompressed point: 0x04, then the coordinates, each the full size for the curve.
+            let (x, y) = (base64url(&x)?, base64url(&y)?);
+            let coordinate_len = (len - 1) / 2;
+            if x.len() != coordinate_len || y.len() != coordinate_len {
+                return None;
+            }
+            let mut point = vec![4];
+            point.extend(x);
+            point.extend(y);
+            Key::ecdsa(point.into())
+        }
+        Jwk::Okp { crv, x } if crv == "Ed25519" => Key::ed25519_from_bytes(base64url(&x)?)?,
+        Jwk::Okp { .. } => return None,
+    })
+}
+
+/// Formats a key as a JWK. Returns `None` for unknown keys.
+pub(super) fn encode(key: &Key) -> Option<Value> {
+    Some(match key {
+        Key::Rsa { keyval, .. } => {
+            let (modulus, exponent) = spki::decode_rsa_public_key(&keyval.public)?;
+            json!({
+                "kty": "RSA",
+                "n": to_base64url(&modulus),
+                "e": to_base64url(&exponent),
+            })
+        }
+        Key::Ecdsa { keyval, .. } => {
+            let crv = if keyval.public.len() == ECDSA_P384_PUBLIC_KEY_LEN {
+                "P-384"
+            } else {
+                "P-256"
+            };
+            let (x, y) = keyval
+                .public
+                .get(1..)?
+                .split_at((keyval.public.len() - 1) / 2);
+            json!({
+                "kty": "EC",
+                "crv": crv,
+                "x": to_base64url(x),
+                "y": to_base64url(y),
+            })
+        }
+        Key::Ed25519 { keyval, .. } => json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": to_base64url(&keyval.public),
+        }),
+        Key::Unknown { .. } => return None,
+    })
+}
+
+fn to_base64url(b: &[u8]) -> String {
+    base64::encode_config(b, base64::URL_SAFE_NO_PAD)
+}
+
+fn base64url(s: &str) -> Option<Vec<u8>> {
+    base64::decode_config(s, base64::URL_SAFE_NO_PAD).ok()
+}
@@ -1,7 +1,9 @@
 #![allow(clippy::use_self)]
 
-use crate::schema::decoded::{Decoded, EcdsaPem, Hex, RsaPem};
+use crate::schema::decoded::{Decoded, EcdsaPem, Hex, RsaPem, ECDSA_P384_PUBLIC_KEY_LEN};
 use crate::schema::error::{self, Result};
+use crate::schema::spki::{self, Parameters};
+use crate::schema::{jwk, openssh};
 use olpc_cjson::CanonicalFormatter;
 use ring::signature::VerificationAlgorithm;
 use serde::{Deserialize, Serialize};
@@ -12,10 +14,36 @@ use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Rsa {
+        keyval: RsaKey,
+        scheme: RsaScheme,
+        _extra: HashMap<String, Value>,
+    },
+    Ed25519 {
+        keyval: Ed25519Key,
+        scheme: Ed25519Scheme,
+        _extra: HashMap<String, Value>,
+    },
+    Ecdsa {
+        keyval: EcdsaKey,
+        scheme: EcdsaScheme,
+        _extra: HashMap<String, Value>,
+    },
+    /// A key whose `keytype` or `scheme` this library doesn't recognize. The original JSON is kept
+    /// so that the key ID and signatures over root still verify, but the key never verifies a
+    /// signature itself.
+    Unknown { original: Value },
+}
+
+/// The serde representation of the keys we recognize. The `Deserialize` and `Serialize`
+/// implementations for `Key` wrap this to handle `Key::Unknown`.
+#[derive(Deserialize, Serialize)]
+#[serde(remote = "Key")]
 #[serde(rename_all = "kebab-case")]
 #[serde(tag = "keytype")]
-pub enum Key {
+enum KeyDef {
     Rsa {
         keyval: RsaKey,
         scheme: RsaScheme,
@@ -37,12 +65,66 @@ pub enum Key {
         #[serde(flatten)]
         _extra: HashMap<String, Value>,
     },
+    #[serde(skip)]
+    Unknown { original: Value },
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error as _;
+
+        let original = Value::deserialize(deserializer)?;
+        match KeyDef::deserialize(&original) {
+            Ok(key) => Ok(key),
+            Err(_) if !is_recognized(&original) => Ok(Key::Unknown { original }),
+            Err(err) => Err(D::Error::custom(err)),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Key::Unknown { original } => original.serialize(serializer),
+            _ => KeyDef::serialize(self, serializer),
+        }
+    }
+}
+
+/// Returns whether a key's `keytype` and `scheme` are ones we support, in which case failing to
+/// parse it is an error rather than a reason to treat it as unknown.
+fn is_recognized(key: &Value) -> bool {
+    fn scheme_is<'a, T: Deserialize<'a>>(scheme: Option<&'a Value>) -> bool {
+        // A missing or non-string scheme is malformed, not unrecognized.
+        match scheme {
+            Some(scheme @ Value::String(_)) => T::deserialize(scheme).is_ok(),
+            _ => true,
+        }
+    }
+
+    let scheme = key.get("scheme");
+    match key.get("keytype").and_then(Value::as_str) {
+        Some("rsa") => scheme_is::<RsaScheme>(scheme),
+        Some("ed25519") => scheme_is::<Ed25519Scheme>(scheme),
+        Some("ecdsa") => scheme_is::<EcdsaScheme>(scheme),
+        Some(_) => false,
+        None => true,
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum RsaScheme {
     RsassaPssSha256,
+    RsassaPssSha384,
+    RsassaPssSha512,
+    RsaPkcs1v15Sha256,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -71,6 +153,7 @@ pub struct Ed25519Key {
 #[serde(rename_all = "kebab-case")]
 pub enum EcdsaScheme {
     EcdsaSha2Nistp256,
+    EcdsaSha2Nistp384,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -93,14 +176,13 @@ impl Key {
     }
 
     /// Verify a signature of an object made with this key.
-    pub(super) fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+    pub fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
         let (alg, public_key): (&dyn VerificationAlgorithm, untrusted::Input<'_>) = match self {
-            Key::Ecdsa {
-                scheme: EcdsaScheme::EcdsaSha2Nistp256,
-                keyval,
-                ..
-            } => (
-                &ring::signature::ECDSA_P256_SHA256_ASN1,
+            Key::Ecdsa { scheme, keyval, .. } => (
+                match scheme {
+                    EcdsaScheme::EcdsaSha2Nistp256 => &ring::signature::ECDSA_P256_SHA256_ASN1,
+                    EcdsaScheme::EcdsaSha2Nistp384 => &ring::signature::ECDSA_P384_SHA384_ASN1,
+                },
                 untrusted::Input::from(&keyval.public),
             ),
             Key::Ed25519 {
@@ -111,14 +193,16 @@ impl Key {
                 &ring::signature::ED25519,
                 untrusted::Input::from(&keyval.public),
             ),
-            Key::Rsa {
-                scheme: RsaScheme::RsassaPssSha256,
-                keyval,
-                ..
-            } => (
-                &ring::signature::RSA_PSS_2048_8192_SHA256,
+            Key::Rsa { scheme, keyval, .. } => (
+                match scheme {
+                    RsaScheme::RsassaPssSha256 => &ring::signature::RSA_PSS_2048_8192_SHA256,
+                    RsaScheme::RsassaPssSha384 => &ring::signature::RSA_PSS_2048_8192_SHA384,
+                    RsaScheme::RsassaPssSha512 => &ring::signature::RSA_PSS_2048_8192_SHA512,
+                    RsaScheme::RsaPkcs1v15Sha256 => &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+                },
                 untrusted::Input::from(&keyval.public),
             ),
+            Key::Unknown { .. } => return false,
         };
 
         alg.verify(
@@ -133,40 +217,154 @@ impl Key {
 impl FromStr for Key {
     type Err = KeyParseError;
 
+    /// Parses a public key in any of these formats:
+    ///
+    /// * PEM-encoded `SubjectPublicKeyInfo` (RSA, ECDSA P-256 and P-384, or Ed25519)
+    /// * a hex-encoded Ed25519 public key
+    /// * a PEM-encoded X.509 certificate, from which the public key is taken
+    /// * an OpenSSH public key line (`ssh-rsa`, `ssh-ed25519`, `ecdsa-sha2-nistp256` or
+    ///   `ecdsa-sha2-nistp384`)
+    /// * a JSON Web Key ([RFC 7517])
+    ///
+    /// RSA keys are given the `rsassa-pss-sha256` scheme.
+    ///
+    /// [RFC 7517]: https://tools.ietf.org/html/rfc7517
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         if let Ok(public) = serde_plain::from_str::<Decoded<RsaPem>>(s) {
-            Ok(Key::Rsa {
-                keyval: RsaKey {
-                    public,
-                    _extra: HashMap::new(),
-                },
-                scheme: RsaScheme::RsassaPssSha256,
-                _extra: HashMap::new(),
-            })
+            Ok(Key::rsa(public))
         } else if let Ok(public) = serde_plain::from_str::<Decoded<Hex>>(s) {
             if public.len() == ring::signature::ED25519_PUBLIC_KEY_LEN {
-                Ok(Key::Ed25519 {
-                    keyval: Ed25519Key {
-                        public,
-                        _extra: HashMap::new(),
-                    },
-                    scheme: Ed25519Scheme::Ed25519,
-                    _extra: HashMap::new(),
-                })
+                Ok(Key::ed25519(public))
             } else {
                 Err(KeyParseError(()))
             }
         } else if let Ok(public) = serde_plain::from_str::<Decoded<EcdsaPem>>(s) {
-            Ok(Key::Ecdsa {
-                keyval: EcdsaKey {
-                    public,
-                    _extra: HashMap::new(),
-                },
-                scheme: EcdsaScheme::EcdsaSha2Nistp256,
+            Ok(Key::ecdsa(public))
+        } else if let Ok(public) = spki::decode(spki::OID_ED25519, Parameters::Absent, s) {
+            Key::ed25519_from_bytes(public).ok_or(KeyParseError(()))
+        } else if let Some(key) = Key::from_certificate(s) {
+            Ok(key)
+        } else if let Some(key) = openssh::decode(s) {
+            Ok(key)
+        } else {
+            jwk::decode(s).ok_or(KeyParseError(()))
+        }
+    }
+}
+
+impl Key {
+    pub(super) fn rsa(public: Decoded<RsaPem>) -> Self {
+        Key::Rsa {
+            keyval: RsaKey {
+                public,
+                _extra: HashMap::new(),
+            },
+            scheme: RsaScheme::RsassaPssSha256,
+            _extra: HashMap::new(),
+        }
+    }
+
+    pub(super) fn ed25519(public: Decoded<Hex>) -> Self {
+        Key::Ed25519 {
+            keyval: Ed25519Key {
+                public,
                 _extra: HashMap::new(),
-            })
+            },
+            scheme: Ed25519Scheme::Ed25519,
+            _extra: HashMap::new(),
+        }
+    }
+
+    /// Returns `None` if `public` is the wrong length for an Ed25519 key.
+    pub(super) fn ed25519_from_bytes(public: Vec<u8>) -> Option<Self> {
+        if public.len() == ring::signature::ED25519_PUBLIC_KEY_LEN {
+            Some(Key::ed25519(public.into()))
         } else {
-            Err(KeyParseError(()))
+            None
+        }
+    }
+
+    /// The scheme is chosen by the curve, which is implied by the length of the point.
+    pub(super) fn ecdsa(public: Decoded<EcdsaPem>) -> Self {
+        Key::Ecdsa {
+            scheme: if public.len() == ECDSA_P384_PUBLIC_KEY_LEN {
+                EcdsaScheme::EcdsaSha2Nistp384
+            } else {
+                EcdsaScheme::EcdsaSha2Nistp256
+            },
+            keyval: EcdsaKey {
+                public,
+                _extra: HashMap::new(),
+            },
+            _extra: HashMap::new(),
+        }
+    }
+
+    /// Returns the DER-encoded `SubjectPublicKeyInfo` document for this key, or `None` for
+    /// [`Key::Unknown`].
+    pub fn to_spki_der(&self) -> Option<Vec<u8>> {
+        let (algorithm_oid, parameters, public) = self.spki_parts()?;
+        Some(spki::encode_der(algorithm_oid, parameters, public))
+    }
+
+    /// Returns the PEM-encoded `SubjectPublicKeyInfo` document for this key, or `None` for
+    /// [`Key::Unknown`].
+    pub fn to_spki_pem(&self) -> Option<String> {
+        let (algorithm_oid, parameters, public) = self.spki_parts()?;
+        Some(spki::encode(algorithm_oid, parameters, public))
+    }
+
+    /// Returns this key as an OpenSSH public key line (without a comment), or `None` for
+    /// [`Key::Unknown`].
+    pub fn to_openssh(&self) -> Option<String> {
+        openssh::encode(self)
+    }
+
+    /// Returns this key as a JSON Web Key, or `None` for [`Key::Unknown`].
+    pub fn to_jwk(&self) -> Option<Value> {
+        jwk::encode(self)
+    }
+
+    fn spki_parts(&self) -> Option<(&'static [u64], Parameters, &[u8])> {
+        match self {
+            Key::Rsa { keyval, .. } => {
+                Some((spki::OID_RSA_ENCRYPTION, Parameters::Null, &keyval.public))
+            }
+            Key::Ed25519 { keyval, .. } => {
+                Some((spki::OID_ED25519, Parameters::Absent, &keyval.public))
+            }
+            Key::Ecdsa { keyval, .. } => Some((
+                spki::OID_EC_PUBLIC_KEY,
+                Parameters::Oid(spki::ecdsa_curve(&keyval.public)),
+                &keyval.public,
+            )),
+            Key::Unknown { .. } => None,
+        }
+    }
+
+    /// Parses the public key out of a PEM-encoded X.509 certificate.
+    fn from_certificate(s: &str) -> Option<Self> {
+        let pem = pem::parse(s).ok().filter(|pem| pem.tag == "CERTIFICATE")?;
+        let spki = spki::certificate_spki(&pem.contents).ok()?;
+        if let Ok(public) = spki::decode_der(spki::OID_RSA_ENCRYPTION, Parameters::Null, &spki) {
+            Some(Key::rsa(public.into()))
+        } else if let Ok(public) = spki::decode_der(
+            spki::OID_EC_PUBLIC_KEY,
+            Parameters::Oid(spki::OID_EC_PARAM_SECP256R1),
+            &spki,
+        )
+        .or_else(|_| {
+            spki::decode_der(
+                spki::OID_EC_PUBLIC_KEY,
+                Parameters::Oid(spki::OID_EC_PARAM_SECP384R1),
+                &spki,
+            )
+        }) {
+            Some(Key::ecdsa(public.into()))
+        } else {
+            spki::decode_der(spki::OID_ED25519, Parameters::Absent, &spki)
+                .ok()
+                .and_then(Key::ed25519_from_bytes)
         }
     }
 }
@@ -181,3 +379,104 @@ impl fmt::Display for KeyParseError {
 }
 
 impl std::error::Error for KeyParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{EcdsaScheme, Key};
+    use serde_json::Value;
+    use std::str::FromStr;
+
+    /// Parses each encoding of the same public key and checks that they all agree.
+    fn parse_all(formats: &[&str]) -> Key {
+        let keys = formats
+            .iter()
+            .map(|s| Key::from_str(s).unwrap())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            assert_eq!(key, &keys[0]);
+        }
+        keys[0].clone()
+    }
+
+    macro_rules! formats {
+        ($name:literal) => {
+            &[
+                include_str!(concat!("../../tests/data/keys/", $name, ".pem")),
+                include_str!(concat!("../../tests/data/keys/", $name, ".crt")),
+                include_str!(concat!("../../tests/data/keys/", $name, ".pub")),
+                include_str!(concat!("../../tests/data/keys/", $name, ".jwk")),
+            ]
+        };
+    }
+
+    #[test]
+    fn rsa_formats() {
+        assert!(matches!(parse_all(formats!("rsa")), Key::Rsa { .. }));
+    }
+
+    #[test]
+    fn ecdsa_formats() {
+        assert!(matches!(
+            parse_all(formats!("p256")),
+            Key::Ecdsa {
+                scheme: EcdsaScheme::EcdsaSha2Nistp256,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_all(formats!("p384")),
+            Key::Ecdsa {
+                scheme: EcdsaScheme::EcdsaSha2Nistp384,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn ed25519_formats() {
+        let key = parse_all(formats!("ed25519"));
+        match &key {
+            Key::Ed25519 { keyval, .. } => {
+                assert_eq!(key, Key::from_str(&hex::encode(&keyval.public)).unwrap());
+            }
+            _ => panic!("not an Ed25519 key: {:?}", key),
+        }
+    }
+
+    #[test]
+    fn export_formats() {
+        for formats in &[
+            formats!("rsa"),
+            formats!("p256"),
+            formats!("p384"),
+            formats!("ed25519"),
+        ] {
+            let key = parse_all(*formats);
+            // These match what openssl and the Python `cryptography` package wrote.
+            assert_eq!(key.to_spki_pem().unwrap(), formats[0].trim());
+            assert!(formats[2].starts_with(&format!("{} ", key.to_openssh().unwrap())));
+            let mut jwk: Value = serde_json::from_str(formats[3]).unwrap();
+            let jwk = jwk.as_object_mut().unwrap();
+            jwk.remove("kid");
+            jwk.remove("use");
+            assert_eq!(&key.to_jwk().unwrap(), &Value::Object(jwk.clone()));
+        }
+    }
+
+    #[test]
+    fn invalid_formats() {
+        for s in &[
+            "",
+            "ssh-rsa",
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5",
+            "ssh-dss AAAAB3NzaC1kc3M=",
+            r#"{"kty":"oct","k":"AAAA"}"#,
+            r#"{"kty":"EC","crv":"P-521","x":"AA","y":"AA"}"#,
+            // The P-256 test key, with a byte of `x` moved to `y`.
+            r#"{"kty":"EC","crv":"P-256","x":"I0T5uH7ZsHUcer1LUA_4sYagvQAovjfZAVoYsGQrPg","y":"82zenD7IS4835Z6Z9xCT5FfiVzTozQNlnrIbRhR_3aG5"}"#,
+            r#"{"kty":"OKP","crv":"Ed25519","x":"AAAA"}"#,
+        ] {
+            assert!(Key::from_str(s).is_err(), "{:?} parsed", s);
+        }
+    }
+}
@@ -2,7 +2,9 @@ mod de;
 pub mod decoded;
 mod error;
 mod iter;
+mod jwk;
 pub mod key;
+mod openssh;
 mod spki;
 mod verify;
 
@@ -0,0 +1,123 @@
+//! Functions for reading and writing OpenSSH public keys, the `ssh-ed25519 AAAA... comment` lines
+//! found in `.pub` and `authorized_keys` files.
+//!
+//! The base64 part is a sequence of length-prefixed strings: the key type again, followed by the
+//! key itself as described in [RFC 4253] (RSA), [RFC 5656] (ECDSA) and [RFC 8709] (Ed25519).
+//!
+//! [RFC 4253]: https://tools.ietf.org/html/rfc4253#section-6.6
+//! [RFC 5656]: https://tools.ietf.org/html/rfc5656#section-3.1
+//! [RFC 8709]: https://tools.ietf.org/html/rfc8709#section-4
+
+use super::decoded::{ECDSA_P256_PUBLIC_KEY_LEN, ECDSA_P384_PUBLIC_KEY_LEN};
+use super::key::Key;
+use super::spki;
+use std::convert::TryFrom;
+
+/// Parses an OpenSSH public key line. Returns `None` if it isn't one or uses an unsupported key
+/// type.
+pub(super) fn decode(s: &str) -> Option<Key> {
+    let mut fields = s.split_whitespace();
+    let keytype = fields.next()?;
+    let blob = base64::decode(fields.next()?).ok()?;
+
+    let mut reader = Reader(&blob);
+    if reader.string()? != keytype.as_bytes() {
+        return None;
+    }
+    let key = match keytype {
+        "ssh-rsa" => {
+            let exponent = reader.string()?;
+            let modulus = reader.string()?;
+            Key::rsa(spki::encode_rsa_public_key(modulus, exponent).into())
+        }
+        "ssh-ed25519" => Key::ed25519_from_bytes(reader.string()?.to_vec())?,
+        "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" => {
+            let expected_len = match reader.string()? {
+                b"nistp256" if keytype == "ecdsa-sha2-nistp256" => ECDSA_P256_PUBLIC_KEY_LEN,
+                b"nistp384" if keytype == "ecdsa-sha2-nistp384" => ECDSA_P384_PUBLIC_KEY_LEN,
+                _ => return None,
+            };
+            let point = reader.string()?;
+            if point.len() != expected_len {
+                return None;
+            }
+            Key::ecdsa(point.to_vec().into())
+        }
+        _ => return None,
+    };
+    if reader.0.is_empty() {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+/// Formats a key as an OpenSSH public key line, without a comment. Returns `None` for unknown keys.
+pub(super) fn encode(key: &Key) -> Option<String> {
+    let mut blob = Vec::new();
+    let keytype = match key {
+        Key::Rsa { keyval, .. } => {
+            let (modulus, exponent) = spki::decode_rsa_public_key(&keyval.public)?;
+            put_string(&mut blob, b"ssh-rsa")?;
+            put_mpint(&mut blob, &exponent)?;
+            put_mpint(&mut blob, &modulus)?;
+            "ssh-rsa"
+        }
+        Key::Ed25519 { keyval, .. } => {
+            put_string(&mut blob, b"ssh-ed25519")?;
+            put_string(&mut blob, &keyval.public)?;
+            "ssh-ed25519"
+        }
+        Key::Ecdsa { keyval, .. } => {
+            let (keytype, curve) = if keyval.public.len() == ECDSA_P384_PUBLIC_KEY_LEN {
+                ("ecdsa-sha2-nistp384", "nistp384")
+            } else {
+                ("ecdsa-sha2-nistp256", "nistp256")
+            };
+            put_string(&mut blob, keytype.as_bytes())?;
+            put_string(&mut blob, curve.as_bytes())?;
+            put_string(&mut blob, &keyval.public)?;
+            keytype
+        }
+        Key::Unknown { .. } => return None,
+    };
+    Some(format!("{} {}", keytype, base64::encode(&blob)))
+}
+
+fn put_string(blob: &mut Vec<u8>, string: &[u8]) -> Option<()> {
+    blob.extend_from_slice(&u32::try_from(string.len()).ok()?.to_be_bytes());
+    blob.extend_from_slice(string);
+    Some(())
+}
+
+/// Writes a positive big-endian integer (without leading zeros) as an `mpint`, which is two's
+/// complement.
+fn put_mpint(blob: &mut Vec<u8>, n: &[u8]) -> Option<()> {
+    if matches!(n.first(), Some(b) if b & 0x80 != 0) {
+        let mut padded = vec![0];
+        padded.extend_from_slice(n);
+        put_string(blob, &padded)
+    } else {
+        put_string(blob, n)
+    }
+}
+
+/// Reads length-prefixed strings from a key blob. RSA's `mpint` values are read as strings too;
+/// they are positive, so at most they have a leading zero byte, which ASN.1 encoding drops anyway.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn string(&mut self) -> Option<&'a [u8]> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let (len, rest) = self.0.split_at(4);
+        let len = usize::try_from(u32::from_be_bytes([len[0], len[1], len[2], len[3]])).ok()?;
+        if rest.len() < len {
+            return None;
+        }
+        let (string, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some(string)
+    }
+}
@@ -17,6 +17,7 @@
 //! [1]: https://github.com/theupdateframework/tuf/blob/49e75ffe5adfc1f883f53f658ace596d14dc0879/tests/repository_data/repository/metadata/root.json#L20
 //! [2]: https://docs.rs/ring/0.14.6/ring/signature/index.html#signing-and-verifying-with-rsa-pkcs1-15-padding
 
+use super::decoded::ECDSA_P384_PUBLIC_KEY_LEN;
 use super::error::{self, Compat, Result};
 use ring::io::der;
 use snafu::{OptionExt, ResultExt};
@@ -24,29 +25,39 @@ use snafu::{OptionExt, ResultExt};
 pub(super) static OID_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113_549, 1, 1, 1];
 pub(super) static OID_EC_PUBLIC_KEY: &[u64] = &[1, 2, 840, 10_045, 2, 1];
 pub(super) static OID_EC_PARAM_SECP256R1: &[u64] = &[1, 2, 840, 10_045, 3, 1, 7];
+pub(super) static OID_EC_PARAM_SECP384R1: &[u64] = &[1, 3, 132, 0, 34];
 
-/// Wrap a bit string in a `SubjectPublicKeyInfo` document.
-pub(super) fn encode(algorithm_oid: &[u64], parameters_oid: Option<&[u64]>, b: &[u8]) -> String {
-    let mut alg_ident = asn1_tag(der::Tag::OID, asn1_encode_oid(algorithm_oid));
-    alg_ident.extend(match parameters_oid {
-        Some(oid) => asn1_tag(der::Tag::OID, asn1_encode_oid(oid)),
-        None => asn1_tag(der::Tag::Null, Vec::new()),
-    });
-    let alg_ident = asn1_tag(der::Tag::Sequence, alg_ident);
+pub(super) static OID_ED25519: &[u64] = &[1, 3, 101, 112];
 
-    let mut bit_string = vec![0];
-    bit_string.extend_from_slice(b);
-    let bit_string = asn1_tag(der::Tag::BitString, bit_string);
-
-    let mut sequence = alg_ident;
-    sequence.extend(bit_string);
+/// The `parameters` field of an `AlgorithmIdentifier`.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Parameters {
+    /// An object identifier, such as the curve of an ECDSA key.
+    Oid(&'static [u64]),
+    /// `NULL`, as used by RSA keys.
+    Null,
+    /// No parameters at all, as used by Ed25519 keys ([RFC 8410]).
+    ///
+    /// [RFC 8410]: https://tools.ietf.org/html/rfc8410#section-3
+    Absent,
+}
 
-    let spki = asn1_tag(der::Tag::Sequence, sequence);
+/// Returns the curve OID for an uncompressed ECDSA public key. The point is a tag byte followed by
+/// both coordinates, so its length identifies the curve.
+pub(super) fn ecdsa_curve(point: &[u8]) -> &'static [u64] {
+    if point.len() == ECDSA_P384_PUBLIC_KEY_LEN {
+        OID_EC_PARAM_SECP384R1
+    } else {
+        OID_EC_PARAM_SECP256R1
+    }
+}
 
+/// Wrap a bit string in a PEM-encoded `SubjectPublicKeyInfo` document.
+pub(super) fn encode(algorithm_oid: &[u64], parameters: Parameters, b: &[u8]) -> String {
     pem::encode_config(
         &pem::Pem {
             tag: "PUBLIC KEY".to_owned(),
-            contents: spki,
+            contents: encode_der(algorithm_oid, parameters, b),
         },
         &pem::EncodeConfig {
             line_ending: pem::LineEnding::LF,
@@ -56,16 +67,45 @@ pub(super) fn encode(algorithm_oid: &[u64], parameters_oid: Option<&[u64]>, b: &
     .to_owned()
 }
 
+/// Wrap a bit string in a DER-encoded `SubjectPublicKeyInfo` document.
+pub(super) fn encode_der(algorithm_oid: &[u64], parameters: Parameters, b: &[u8]) -> Vec<u8> {
+    let mut alg_ident = asn1_tag(der::Tag::OID, asn1_encode_oid(algorithm_oid));
+    match parameters {
+        Parameters::Oid(oid) => alg_ident.extend(asn1_tag(der::Tag::OID, asn1_encode_oid(oid))),
+        Parameters::Null => alg_ident.extend(asn1_tag(der::Tag::Null, Vec::new())),
+        Parameters::Absent => {}
+    }
+    let alg_ident = asn1_tag(der::Tag::Sequence, alg_ident);
+
+    let mut bit_string = vec![0];
+    bit_string.extend_from_slice(b);
+    let bit_string = asn1_tag(der::Tag::BitString, bit_string);
+
+    let mut sequence = alg_ident;
+    sequence.extend(bit_string);
+
+    asn1_tag(der::Tag::Sequence, sequence)
+}
+
 /// Extract the bit string from a PEM-encoded `SubjectPublicKeyInfo` document.
 pub(super) fn decode(
     algorithm_oid: &[u64],
-    parameters_oid: Option<&[u64]>,
+    parameters: Parameters,
     input: &str,
 ) -> Result<Vec<u8>> {
     let pem = pem::parse(input)
         .map_err(Compat)
         .context(error::PemDecode)?;
-    Ok(untrusted::Input::from(&pem.contents)
+    decode_der(algorithm_oid, parameters, &pem.contents)
+}
+
+/// Extract the bit string from a DER-encoded `SubjectPublicKeyInfo` document.
+pub(super) fn decode_der(
+    algorithm_oid: &[u64],
+    parameters: Parameters,
+    input: &[u8],
+) -> Result<Vec<u8>> {
+    Ok(untrusted::Input::from(input)
         .read_all(ring::error::Unspecified, |input| {
             der::expect_tag_and_get_value(input, der::Tag::Sequence).and_then(|spki| {
                 spki.read_all(ring::error::Unspecified, |input| {
@@ -77,14 +117,18 @@ pub(super) fn decode(
                                 {
                                     return Err(ring::error::Unspecified);
                                 }
-                                if let Some(parameters_oid) = parameters_oid {
-                                    if der::expect_tag_and_get_value(input, der::Tag::OID)?
-                                        != untrusted::Input::from(&asn1_encode_oid(parameters_oid))
-                                    {
-                                        return Err(ring::error::Unspecified);
+                                match parameters {
+                                    Parameters::Oid(oid) => {
+                                        if der::expect_tag_and_get_value(input, der::Tag::OID)?
+                                            != untrusted::Input::from(&asn1_encode_oid(oid))
+                                        {
+                                            return Err(ring::error::Unspecified);
+                                        }
+                                    }
+                                    Parameters::Null => {
+                                        der::expect_tag_and_get_value(input, der::Tag::Null)?;
                                     }
-                                } else {
-                                    der::expect_tag_and_get_value(input, der::Tag::Null)?;
+                                    Parameters::Absent => {}
                                 }
                                 Ok(())
                             })
@@ -100,6 +144,82 @@ pub(super) fn decode(
         .to_owned())
 }
 
+/// Extract the DER-encoded `SubjectPublicKeyInfo` document from a DER-encoded X.509 certificate.
+///
+/// The certificate itself is not verified in any way; this only finds the public key in it.
+pub(super) fn certificate_spki(input: &[u8]) -> Result<Vec<u8>> {
+    untrusted::Input::from(input)
+        .read_all(ring::error::Unspecified, |input| {
+            // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signatureValue }
+            let certificate = der::expect_tag_and_get_value(input, der::Tag::Sequence)?;
+            certificate.read_all(ring::error::Unspecified, |input| {
+                let tbs_certificate = der::expect_tag_and_get_value(input, der::Tag::Sequence)?;
+                input.skip_to_end();
+                tbs_certificate.read_all(ring::error::Unspecified, |input| {
+                    // version [0] EXPLICIT is optional
+                    if input.peek(der::Tag::ContextSpecificConstructed0.into()) {
+                        der::expect_tag_and_get_value(
+                            input,
+                            der::Tag::ContextSpecificConstructed0,
+                        )?;
+                    }
+                    der::expect_tag_and_get_value(input, der::Tag::Integer)?; // serialNumber
+                    der::expect_tag_and_get_value(input, der::Tag::Sequence)?; // signature
+                    der::expect_tag_and_get_value(input, der::Tag::Sequence)?; // issuer
+                    der::expect_tag_and_get_value(input, der::Tag::Sequence)?; // validity
+                    der::expect_tag_and_get_value(input, der::Tag::Sequence)?; // subject
+                    let spki = der::expect_tag_and_get_value(input, der::Tag::Sequence)?;
+                    input.skip_to_end(); // unique IDs and extensions
+                    Ok(spki)
+                })
+            })
+        })
+        .ok()
+        .context(error::CertificateDecode)
+        .map(|spki| asn1_tag(der::Tag::Sequence, spki.as_slice_less_safe().to_owned()))
+}
+
+/// Encode an `RSAPublicKey` document (the format `ring` uses for RSA public keys) from the
+/// big-endian modulus and public exponent.
+pub(super) fn encode_rsa_public_key(modulus: &[u8], exponent: &[u8]) -> Vec<u8> {
+    let mut sequence = asn1_encode_integer(modulus);
+    sequence.extend(asn1_encode_integer(exponent));
+    asn1_tag(der::Tag::Sequence, sequence)
+}
+
+/// Decode an `RSAPublicKey` document into the big-endian modulus and public exponent.
+pub(super) fn decode_rsa_public_key(input: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    untrusted::Input::from(input)
+        .read_all(ring::error::Unspecified, |input| {
+            der::nested(
+                input,
+                der::Tag::Sequence,
+                ring::error::Unspecified,
+                |input| {
+                    let modulus = der::positive_integer(input)?;
+                    let exponent = der::positive_integer(input)?;
+                    Ok((
+                        modulus.big_endian_without_leading_zero().to_owned(),
+                        exponent.big_endian_without_leading_zero().to_owned(),
+                    ))
+                },
+            )
+        })
+        .ok()
+}
+
+/// Encode an unsigned big-endian integer in ASN.1.
+fn asn1_encode_integer(b: &[u8]) -> Vec<u8> {
+    let b = &b[b.iter().position(|b| *b != 0).unwrap_or(b.len())..];
+    let mut v = Vec::with_capacity(b.len() + 1);
+    // A set high bit would make the integer negative.
+    if b.is_empty() || b[0] & 0x80 != 0 {
+        v.push(0);
+    }
+    v.extend_from_slice(b);
+    asn1_tag(der::Tag::Integer, v)
+}
+
 fn asn1_tag(tag: der::Tag, data: Vec<u8>) -> Vec<u8> {
     let mut v = vec![tag as u8];
     v.extend(asn1_encode_len(data.len()));
@@ -154,7 +274,10 @@ fn to_vlq(n: u64) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use super::{asn1_encode_len, asn1_encode_oid, to_vlq, OID_RSA_ENCRYPTION};
+    use super::{
+        asn1_encode_integer, asn1_encode_len, asn1_encode_oid, to_vlq, OID_EC_PARAM_SECP384R1,
+        OID_RSA_ENCRYPTION,
+    };
 
     #[test]
     fn test_asn1_encode_len() {
@@ -166,12 +289,31 @@ mod tests {
         assert_eq!(asn1_encode_len(0xffff_ffff), [0x84, 0xff, 0xff, 0xff, 0xff]);
     }
 
+    #[test]
+    fn test_asn1_encode_integer() {
+        assert_eq!(asn1_encode_integer(&[]), [0x02, 0x01, 0x00]);
+        assert_eq!(asn1_encode_integer(&[0x00, 0x00]), [0x02, 0x01, 0x00]);
+        assert_eq!(
+            asn1_encode_integer(&[0x01, 0x00, 0x01]),
+            [0x02, 0x03, 0x01, 0x00, 0x01]
+        );
+        assert_eq!(asn1_encode_integer(&[0x00, 0x80]), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(
+            asn1_encode_integer(&[0xff, 0x01]),
+            [0x02, 0x03, 0x00, 0xff, 0x01]
+        );
+    }
+
     #[test]
     fn test_asn1_encode_oid() {
         assert_eq!(
             asn1_encode_oid(OID_RSA_ENCRYPTION),
             [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01]
         );
+        assert_eq!(
+            asn1_encode_oid(OID_EC_PARAM_SECP384R1),
+            [0x2b, 0x81, 0x04, 0x00, 0x22]
+        );
     }
 
     #[test]
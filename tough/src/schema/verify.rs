@@ -1,3 +1,4 @@
+use super::decoded::{Decoded, Hex};
 use super::error::{self, Result};
 use super::{Role, Root, Signed};
 use olpc_cjson::CanonicalFormatter;
@@ -10,7 +11,33 @@ impl Root {
             .roles
             .get(&T::TYPE)
             .context(error::MissingRole { role: T::TYPE })?;
-        let mut valid = 0;
+        let valid = self.valid_signers(role)?.len() as u64;
+
+        ensure!(
+            valid >= u64::from(role_keys.threshold),
+            error::SignatureThreshold {
+                role: T::TYPE,
+                threshold: role_keys.threshold,
+                valid,
+            }
+        );
+        Ok(())
+    }
+
+    /// Returns the key ID of each signature on `role` that was made by a key this root trusts for
+    /// the role and that verifies. This is what [`Root::verify_role`] counts against the role's
+    /// threshold.
+    ///
+    /// Each key ID is listed at most once, so repeating a signature doesn't count toward the
+    /// threshold more than once.
+    pub fn valid_signers<T: Role + Serialize>(
+        &self,
+        role: &Signed<T>,
+    ) -> Result<Vec<Decoded<Hex>>> {
+        let role_keys = self
+            .roles
+            .get(&T::TYPE)
+            .context(error::MissingRole { role: T::TYPE })?;
 
         let mut data = Vec::new();
         let mut ser = serde_json::Serializer::with_formatter(&mut data, CanonicalFormatter::new());
@@ -20,37 +47,88 @@ impl Root {
                 what: format!("{} role", T::TYPE),
             })?;
 
+        let mut valid = Vec::new();
         for signature in &role.signatures {
+            if valid.contains(&signature.keyid) {
+                continue;
+            }
             if role_keys.keyids.contains(&signature.keyid) {
                 if let Some(key) = self.keys.get(&signature.keyid) {
                     if key.verify(&data, &signature.sig) {
-                        valid += 1;
+                        valid.push(signature.keyid.clone());
                     }
                 }
             }
         }
-
-        ensure!(
-            valid >= u64::from(role_keys.threshold),
-            error::SignatureThreshold {
-                role: T::TYPE,
-                threshold: role_keys.threshold,
-                valid,
-            }
-        );
-        Ok(())
+        Ok(valid)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Root, Signed};
+    use crate::schema::key::Key;
 
     #[test]
     fn simple_rsa() {
         let root: Signed<Root> =
             serde_json::from_str(include_str!("../../tests/data/simple-rsa/root.json")).unwrap();
         root.signed.verify_role(&root).unwrap();
+        assert_eq!(
+            root.signed.valid_signers(&root).unwrap(),
+            vec![root.signatures[0].keyid.clone()]
+        );
+    }
+
+    #[test]
+    fn additional_schemes() {
+        // Signed with openssl using rsa-pkcs1v15-sha256, rsassa-pss-sha384, rsassa-pss-sha512 and
+        // ecdsa-sha2-nistp384 keys, with a threshold of 4.
+        let root: Signed<Root> = serde_json::from_str(include_str!(
+            "../../tests/data/additional-schemes/root.json"
+        ))
+        .unwrap();
+        root.signed.verify_role(&root).unwrap();
+        assert_eq!(root.signed.valid_signers(&root).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn duplicate_signatures() {
+        // Repeating one valid signature four times must not meet a threshold of 4.
+        let mut root: Signed<Root> = serde_json::from_str(include_str!(
+            "../../tests/data/additional-schemes/root.json"
+        ))
+        .unwrap();
+        let signature = root.signatures[0].clone();
+        root.signatures = vec![signature; 4];
+        assert_eq!(
+            root.signed.valid_signers(&root).unwrap(),
+            vec![root.signatures[0].keyid.clone()]
+        );
+        root.signed
+            .verify_role(&root)
+            .expect_err("duplicate signatures should count once");
+    }
+
+    #[test]
+    fn unknown_keys() {
+        // Lists an RSA key, a key with an unknown type, and the same RSA key with an unknown
+        // scheme. The last two have signatures, but only the first key's signature counts.
+        let root: Signed<Root> =
+            serde_json::from_str(include_str!("../../tests/data/unknown-keys/root.json")).unwrap();
+        assert_eq!(
+            root.signed
+                .keys
+                .values()
+                .filter(|key| matches!(key, Key::Unknown { .. }))
+                .count(),
+            2
+        );
+        root.signed.verify_role(&root).unwrap();
+        assert_eq!(
+            root.signed.valid_signers(&root).unwrap(),
+            vec![root.signatures[2].keyid.clone()]
+        );
     }
 
     #[test]
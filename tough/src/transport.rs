@@ -1,3 +1,5 @@
+use snafu::Snafu;
+use std::collections::HashMap;
 use std::io::Read;
 use url::Url;
 
@@ -40,3 +42,157 @@ impl Transport for reqwest::Client {
         self.get(url.as_str()).send()?.error_for_status()
     }
 }
+
+/// A `Transport` that hands each request to another transport based on the URL's scheme.
+///
+/// `file` URLs are fetched with [`FilesystemTransport`], and with the `http` feature enabled,
+/// `http` and `https` URLs are fetched with [`HttpTransport`]. Transports for other schemes can be
+/// added with [`SchemeTransport::register`].
+pub struct SchemeTransport {
+    transports: HashMap<String, Box<dyn DynTransport>>,
+}
+
+impl SchemeTransport {
+    /// Creates a `SchemeTransport` that handles the built-in schemes.
+    pub fn new() -> Self {
+        let mut transport = Self {
+            transports: HashMap::new(),
+        };
+        transport.register("file", FilesystemTransport);
+        #[cfg(feature = "http")]
+        {
+            let client = HttpTransport::new();
+            transport.register("http", client.clone());
+            transport.register("https", client);
+        }
+        transport
+    }
+
+    /// Uses `transport` for URLs with the scheme `scheme`, replacing any transport previously
+    /// registered for it.
+    pub fn register<T>(&mut self, scheme: &str, transport: T)
+    where
+        T: Transport + Send + Sync + 'static,
+        T::Stream: Send + 'static,
+    {
+        self.transports
+            .insert(scheme.to_ascii_lowercase(), Box::new(transport));
+    }
+}
+
+impl Default for SchemeTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for SchemeTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut schemes = self.transports.keys().collect::<Vec<_>>();
+        schemes.sort();
+        f.debug_struct("SchemeTransport")
+            .field("schemes", &schemes)
+            .finish()
+    }
+}
+
+impl Transport for SchemeTransport {
+    type Stream = Box<dyn Read + Send>;
+    type Error = SchemeTransportError;
+
+    fn fetch(&self, url: Url) -> Result<Self::Stream, Self::Error> {
+        let transport = self.transports.get(url.scheme()).ok_or_else(|| {
+            SchemeTransportError::UnsupportedScheme {
+                scheme: url.scheme().to_owned(),
+            }
+        })?;
+        transport
+            .fetch_boxed(url)
+            .map_err(|source| SchemeTransportError::Fetch { source })
+    }
+}
+
+/// The error type for [`SchemeTransport`].
+#[derive(Debug, Snafu)]
+pub enum SchemeTransportError {
+    /// No transport is registered for the URL's scheme.
+    #[snafu(display("No transport registered for URL scheme \"{}\"", scheme))]
+    UnsupportedScheme {
+        /// The URL scheme
+        scheme: String,
+    },
+
+    /// The transport for the URL's scheme failed.
+    #[snafu(display("{}", source))]
+    Fetch {
+        /// The error from the underlying transport
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+/// An object-safe `Transport`, so that transports with different stream and error types can be
+/// stored together.
+trait DynTransport: Send + Sync {
+    fn fetch_boxed(
+        &self,
+        url: Url,
+    ) -> Result<Box<dyn Read + Send>, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+impl<T> DynTransport for T
+where
+    T: Transport + Send + Sync,
+    T::Stream: Send + 'static,
+{
+    fn fetch_boxed(
+        &self,
+        url: Url,
+    ) -> Result<Box<dyn Read + Send>, Box<dyn std::error::Error + Send + Sync>> {
+        match self.fetch(url) {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchemeTransport, SchemeTransportError, Transport};
+    use std::io::Read;
+    use url::Url;
+
+    struct Echo;
+
+    impl Transport for Echo {
+        type Stream = std::io::Cursor<String>;
+        type Error = std::io::Error;
+
+        fn fetch(&self, url: Url) -> Result<Self::Stream, Self::Error> {
+            Ok(std::io::Cursor::new(url.path().to_owned()))
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut transport = SchemeTransport::new();
+        match transport.fetch(Url::parse("echo:hello").unwrap()) {
+            Err(SchemeTransportError::UnsupportedScheme { scheme }) => assert_eq!(scheme, "echo"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        transport.register("echo", Echo);
+        let mut buf = String::new();
+        transport
+            .fetch(Url::parse("echo:hello").unwrap())
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "hello");
+
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/data/simple-rsa/root.json"
+        );
+        assert!(transport.fetch(Url::from_file_path(path).unwrap()).is_ok());
+    }
+}
@@ -3,9 +3,13 @@
 
 use std::fs::File;
 use std::io::Read;
+use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tempfile::TempDir;
-use tough::{Limits, Repository, Settings};
+use tough::error::Error;
+use tough::schema::RoleType;
+use tough::{Limits, Observer, Repository, Settings};
 use url::Url;
 
 fn test_data() -> PathBuf {
@@ -44,6 +48,7 @@ fn test_tuf_reference_impl() {
             metadata_base_url,
             target_base_url,
             limits: Limits::default(),
+            observer: None,
         },
     )
     .unwrap();
@@ -65,4 +70,274 @@ fn test_tuf_reference_impl() {
             .unwrap(),
         "0644"
     );
+
+    assert_eq!(repo.root().signed.version.get(), 1);
+    assert!(!repo.root().signed.consistent_snapshot);
+    assert_eq!(repo.snapshot().signed.version.get(), 1);
+    assert_eq!(repo.timestamp().signed.version.get(), 1);
+    assert_eq!(repo.targets_version().get(), 1);
+    assert_eq!(
+        repo.targets_expires().to_rfc3339(),
+        "2030-01-01T00:00:00+00:00"
+    );
+}
+
+/// Test that targets can be read from many threads at once. Each read samples the system time and
+/// stores it in the datastore, which must not race.
+#[test]
+fn test_concurrent_read_target() {
+    let base = test_data().join("tuf-reference-impl");
+    let datastore = TempDir::new().unwrap();
+    let metadata_base_url = &dir_url(base.join("metadata"));
+    let target_base_url = &dir_url(base.join("targets"));
+    let repo = Repository::load(
+        &tough::FilesystemTransport,
+        Settings {
+            root: File::open(base.join("metadata").join("1.root.json")).unwrap(),
+            datastore: datastore.as_ref(),
+            metadata_base_url,
+            target_base_url,
+            limits: Limits::default(),
+            observer: None,
+        },
+    )
+    .unwrap();
+
+    std::thread::scope(|scope| {
+        for _ in 0..16 {
+            scope.spawn(|| {
+                for _ in 0..200 {
+                    assert_eq!(
+                        read_to_end(repo.read_target("file1.txt").unwrap().unwrap()),
+                        &b"This is an example target file."[..]
+                    );
+                }
+            });
+        }
+    });
+}
+
+/// Loads the reference implementation's repository with the given limits.
+fn load_tuf_reference_impl(limits: Limits) -> tough::error::Result<()> {
+    let base = test_data().join("tuf-reference-impl");
+    let datastore = TempDir::new().unwrap();
+    Repository::load(
+        &tough::FilesystemTransport,
+        Settings {
+            root: File::open(base.join("metadata").join("1.root.json")).unwrap(),
+            datastore: datastore.as_ref(),
+            metadata_base_url: &dir_url(base.join("metadata")),
+            target_base_url: &dir_url(base.join("targets")),
+            limits,
+            observer: None,
+        },
+    )
+    .map(|_| ())
+}
+
+/// Test that each metadata limit rejects a repository that exceeds it, and accepts one that is
+/// exactly at it.
+#[test]
+fn test_limits() {
+    // The timestamp lists a 556-byte snapshot; the root lists 4 keys; each role has 1 signature;
+    // and there are 2 targets.
+    let at_limits = Limits {
+        max_snapshot_size: 556,
+        max_targets: 2,
+        max_signatures: 1,
+        max_keys: 4,
+        ..Limits::default()
+    };
+    load_tuf_reference_impl(at_limits.clone()).unwrap();
+
+    match load_tuf_reference_impl(Limits {
+        max_snapshot_size: 555,
+        ..at_limits.clone()
+    }) {
+        Err(Error::MaxSnapshotSizeExceeded { size: 556, .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match load_tuf_reference_impl(Limits {
+        max_targets: 1,
+        ..at_limits.clone()
+    }) {
+        Err(Error::MaxTargetsExceeded { max_targets: 1, .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match load_tuf_reference_impl(Limits {
+        max_signatures: 0,
+        ..at_limits.clone()
+    }) {
+        Err(Error::MaxSignaturesExceeded {
+            role: RoleType::Root,
+            max_signatures: 0,
+            ..
+        }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match load_tuf_reference_impl(Limits {
+        max_keys: 3,
+        ..at_limits
+    }) {
+        Err(Error::MaxKeysExceeded { count: 4, .. }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// Records observer events as strings, naming URLs by their last path segment.
+#[derive(Default)]
+struct RecordingObserver(Mutex<Vec<String>>);
+
+impl RecordingObserver {
+    fn record(&self, event: String) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+fn file_name(url: &Url) -> &str {
+    url.path_segments().unwrap().next_back().unwrap()
+}
+
+impl Observer for RecordingObserver {
+    fn fetch_started(&self, role: RoleType, url: &Url) {
+        self.record(format!("started {} {}", role, file_name(url)));
+    }
+
+    fn fetch_finished(&self, role: RoleType, url: &Url, bytes: u64) {
+        self.record(format!("finished {} {} {}", role, file_name(url), bytes));
+    }
+
+    fn fetch_failed(&self, role: RoleType, url: &Url, bytes: u64, _error: &Error) {
+        self.record(format!("failed {} {} {}", role, file_name(url), bytes));
+    }
+
+    fn root_updated(&self, old_version: NonZeroU64, new_version: NonZeroU64) {
+        self.record(format!("root {} -> {}", old_version, new_version));
+    }
+
+    fn role_verified(&self, role: RoleType, version: NonZeroU64) {
+        self.record(format!("verified {} {}", role, version));
+    }
+
+    fn role_rejected(&self, role: RoleType, _error: &Error) {
+        self.record(format!("rejected {}", role));
+    }
+
+    fn datastore_written(&self, file: &str) {
+        self.record(format!("wrote {}", file));
+    }
+
+    fn target_progress(&self, name: &str, bytes: u64, max_size: u64) {
+        self.record(format!("progress {} {}/{}", name, bytes, max_size));
+    }
+}
+
+/// Test that the observer in `Settings` sees each metadata fetch, datastore write, and target read.
+#[test]
+fn test_observer() {
+    let base = test_data().join("tuf-reference-impl");
+    let datastore = TempDir::new().unwrap();
+    let observer = RecordingObserver::default();
+
+    let metadata_base_url = &dir_url(base.join("metadata"));
+    let target_base_url = &dir_url(base.join("targets"));
+    let repo = Repository::load(
+        &tough::FilesystemTransport,
+        Settings {
+            root: File::open(base.join("metadata").join("1.root.json")).unwrap(),
+            datastore: datastore.as_ref(),
+            metadata_base_url,
+            target_base_url,
+            limits: Limits::default(),
+            observer: Some(&observer),
+        },
+    )
+    .unwrap();
+    let file_size = |name: &str| {
+        std::fs::metadata(base.join("metadata").join(name))
+            .unwrap()
+            .len()
+    };
+    assert_eq!(
+        *observer.0.lock().unwrap(),
+        vec![
+            "started root 2.root.json".to_owned(),
+            "wrote latest_known_time.json".to_owned(),
+            "verified root 1".to_owned(),
+            "started timestamp timestamp.json".to_owned(),
+            format!(
+                "finished timestamp timestamp.json {}",
+                file_size("timestamp.json")
+            ),
+            "wrote latest_known_time.json".to_owned(),
+            "verified timestamp 1".to_owned(),
+            "wrote timestamp.json".to_owned(),
+            "started snapshot snapshot.json".to_owned(),
+            format!(
+                "finished snapshot snapshot.json {}",
+                file_size("snapshot.json")
+            ),
+            "wrote latest_known_time.json".to_owned(),
+            "verified snapshot 1".to_owned(),
+            "wrote snapshot.json".to_owned(),
+            "started targets targets.json".to_owned(),
+            format!(
+                "finished targets targets.json {}",
+                file_size("targets.json")
+            ),
+            "wrote latest_known_time.json".to_owned(),
+            "verified targets 1".to_owned(),
+            "wrote targets.json".to_owned(),
+        ]
+    );
+
+    observer.0.lock().unwrap().clear();
+    read_to_end(repo.read_target("file1.txt").unwrap().unwrap());
+    assert_eq!(
+        observer.0.lock().unwrap().as_slice(),
+        ["wrote latest_known_time.json", "progress file1.txt 31/31"]
+    );
+}
+
+/// Test that metadata that fails verification after it is fetched is reported as rejected.
+#[test]
+fn test_observer_rejected() {
+    let base = test_data().join("tuf-reference-impl");
+    let metadata = TempDir::new().unwrap();
+    for entry in std::fs::read_dir(base.join("metadata")).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, metadata.path().join(path.file_name().unwrap())).unwrap();
+    }
+    // snapshot.json lists no hashes for targets.json, so a bad signature is only found when the
+    // signatures are verified.
+    let targets = metadata.path().join("targets.json");
+    let contents = std::fs::read_to_string(&targets).unwrap();
+    std::fs::write(&targets, contents.replace("\"sig\": \"d", "\"sig\": \"0")).unwrap();
+
+    let datastore = TempDir::new().unwrap();
+    let observer = RecordingObserver::default();
+    let metadata_base_url = &dir_url(metadata.path());
+    let target_base_url = &dir_url(base.join("targets"));
+    let result = Repository::load(
+        &tough::FilesystemTransport,
+        Settings {
+            root: File::open(metadata.path().join("1.root.json")).unwrap(),
+            datastore: datastore.as_ref(),
+            metadata_base_url,
+            target_base_url,
+            limits: Limits::default(),
+            observer: Some(&observer),
+        },
+    );
+    match result {
+        Err(Error::VerifyMetadata {
+            role: RoleType::Targets,
+            ..
+        }) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert_eq!(
+        observer.0.lock().unwrap().last().map(String::as_str),
+        Some("rejected targets")
+    );
 }
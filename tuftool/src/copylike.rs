@@ -11,6 +11,8 @@ pub(crate) enum Copylike {
     Copy,
     Hardlink,
     Symlink,
+    /// Copy-on-write clone, falling back to a copy where the filesystem doesn't support it
+    Reflink,
 }
 
 impl Copylike {
@@ -29,6 +31,12 @@ impl Copylike {
         match self {
             Copylike::Copy => fs::copy(src, dst).map(|_| ()),
             Copylike::Hardlink => fs::hard_link(src, dst),
+            Copylike::Reflink => {
+                if !reflink(src.as_ref(), dst.as_ref())? {
+                    fs::copy(src, dst)?;
+                }
+                Ok(())
+            }
             Copylike::Symlink => {
                 #[cfg(unix)]
                 {
@@ -44,6 +52,57 @@ impl Copylike {
     }
 }
 
+/// Clones `src` to `dst` with the FICLONE ioctl. Returns `Ok(false)` if the filesystem (or
+/// platform) doesn't support cloning, in which case `dst` is not created.
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let src_file = fs::File::open(src)?;
+    let dst_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    // The request parameter is an unsigned long on glibc but an int on musl, and FICLONE's value
+    // and type vary by architecture.
+    let ret = unsafe {
+        libc::ioctl(
+            dst_file.as_raw_fd(),
+            libc::FICLONE as _,
+            src_file.as_raw_fd(),
+        )
+    };
+    if ret == 0 {
+        dst_file.set_permissions(src_file.metadata()?.permissions())?;
+        return Ok(true);
+    }
+
+    let err = io::Error::last_os_error();
+    drop(dst_file);
+    fs::remove_file(dst)?;
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EXDEV | libc::EINVAL | libc::ENOSYS) => {
+            Ok(false)
+        }
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Returns whether files can be reflinked within the filesystem holding `dir`, by cloning a
+/// scratch file there. `dir` is created if it doesn't exist.
+pub(crate) fn reflink_supported(dir: &Path) -> io::Result<bool> {
+    fs::create_dir_all(dir)?;
+    let scratch = tempfile::tempdir_in(dir)?;
+    let src = scratch.path().join("src");
+    fs::write(&src, b"reflink")?;
+    reflink(&src, &scratch.path().join("dst"))
+}
+
 impl Display for Copylike {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -53,6 +112,7 @@ impl Display for Copylike {
                 Copylike::Copy => "copy",
                 Copylike::Hardlink => "hardlink",
                 Copylike::Symlink => "symlink",
+                Copylike::Reflink => "reflink",
             }
         )
     }
@@ -60,7 +120,7 @@ impl Display for Copylike {
 
 #[cfg(test)]
 mod tests {
-    use super::Copylike;
+    use super::{reflink_supported, Copylike};
     use std::fs;
     use std::io;
 
@@ -75,7 +135,12 @@ mod tests {
             fs::File::create(&b)?;
         }
 
-        for copy_action in &[Copylike::Copy, Copylike::Hardlink, Copylike::Symlink] {
+        for copy_action in &[
+            Copylike::Copy,
+            Copylike::Hardlink,
+            Copylike::Symlink,
+            Copylike::Reflink,
+        ] {
             eprintln!("{:?}", copy_action);
             let target = dir.path().join(copy_action.to_string());
             copy_action.run(&a, &target)?;
@@ -87,4 +152,14 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn probe_cleans_up() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let outdir = dir.path().join("outdir");
+        // Either answer is fine, depending on the filesystem running the test.
+        reflink_supported(&outdir)?;
+        assert_eq!(fs::read_dir(&outdir)?.count(), 0);
+        Ok(())
+    }
 }
@@ -1,22 +1,28 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::copylike::Copylike;
+use crate::copylike::{self, Copylike};
 use crate::datetime::parse_datetime;
 use crate::error::{self, Result};
+use crate::hash_cache::HashCache;
 use crate::key::{keys_for_root, sign_metadata, RootKeys};
 use crate::source::KeySource;
 use chrono::{DateTime, Utc};
+use jsonschema::JSONSchema;
 use maplit::hashmap;
 use rayon::prelude::*;
+use regex::bytes::Regex;
 use ring::rand::SystemRandom;
 use serde::Serialize;
+use serde_json::Value;
 use sha2::{Digest, Sha256};
-use snafu::{OptionExt, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
 use std::collections::HashMap;
-use std::fs::File;
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, Metadata};
+use std::io::Read;
 use std::num::{NonZeroU64, NonZeroUsize};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use structopt::StructOpt;
 use tough::schema::{
     decoded::Decoded, Hashes, Role, RoleType, Root, Signed, Snapshot, SnapshotMeta, Target,
@@ -24,7 +30,11 @@ use tough::schema::{
 };
 use walkdir::WalkDir;
 
+/// Suffix of the files read by `--custom-sidecars`.
+const SIDECAR_SUFFIX: &str = ".custom.json";
+
 #[derive(Debug, StructOpt)]
+#[allow(clippy::struct_excessive_bools)]
 pub(crate) struct CreateArgs {
     /// Copy files into `outdir` instead of symlinking them
     #[structopt(short = "c", long = "copy")]
@@ -32,6 +42,14 @@ pub(crate) struct CreateArgs {
     /// Hardlink files into `outdir` instead of symlinking them
     #[structopt(short = "H", long = "hardlink")]
     hardlink: bool,
+    /// Clone files into `outdir` with copy-on-write (on filesystems such as btrfs and XFS),
+    /// copying them where that isn't supported
+    #[structopt(long = "reflink")]
+    reflink: bool,
+    /// Pick the cheapest safe way to put files into `outdir`: reflink them if its filesystem
+    /// supports it, otherwise copy them. Files are never hardlinked or symlinked.
+    #[structopt(long = "auto")]
+    auto: bool,
 
     /// Follow symbolic links in `indir`
     #[structopt(short = "f", long = "follow")]
@@ -73,10 +91,69 @@ pub(crate) struct CreateArgs {
     #[structopt(short = "r", long = "root")]
     root: PathBuf,
 
-    /// Directory of targets
-    indir: PathBuf,
+    /// JSON or TOML file mapping target names to custom metadata objects
+    #[structopt(long = "custom-manifest")]
+    custom_manifest: Option<PathBuf>,
+    /// Read custom metadata for each target from `<file>.custom.json` next to it in `indir`;
+    /// fields from these files override fields from --custom-manifest
+    #[structopt(long = "custom-sidecars")]
+    custom_sidecars: bool,
+    /// JSON Schema that every target's custom metadata must match
+    #[structopt(long = "custom-schema")]
+    custom_schema: Option<PathBuf>,
+
+    /// Record each file's permissions as `file_permissions` in its custom metadata
+    #[structopt(long = "record-permissions")]
+    record_permissions: bool,
+    /// Record each file's modification time as `modified` in its custom metadata
+    #[structopt(long = "record-mtime")]
+    record_mtime: bool,
+    /// Record each target's media type, guessed from its name, as `media_type` in its custom
+    /// metadata
+    #[structopt(long = "record-media-type")]
+    record_media_type: bool,
+    /// Version signature: search each file for this regular expression and record the first
+    /// match (or its first capture group) as `version` in its custom metadata; matches must be
+    /// shorter than 4 KiB
+    #[structopt(long = "record-version")]
+    record_version: Option<Regex>,
+
+    /// Cache target hashes, keyed by path, size, inode and modification time, so that unchanged
+    /// files are not re-hashed
+    #[structopt(long = "hash-cache")]
+    hash_cache: bool,
+    /// File to store the hash cache in (implies --hash-cache; default:
+    /// `.OUTDIR.tuftool-hash-cache.json` next to `outdir`, so it is not published)
+    #[structopt(long = "hash-cache-file")]
+    hash_cache_file: Option<PathBuf>,
+    /// Re-hash every file even if it has a hash cache entry, and report stale entries
+    #[structopt(long = "verify-cache")]
+    verify_cache: bool,
+
+    /// JSON or TOML file mapping target names to source files, used instead of or in addition
+    /// to `indir`; relative paths are relative to the manifest's directory
+    #[structopt(long = "targets-manifest")]
+    targets_manifest: Option<PathBuf>,
+
+    /// Directory of targets (may be omitted with --targets-manifest, giving --outdir instead of
+    /// `outdir`)
+    #[structopt(required_unless = "targets-manifest")]
+    indir: Option<PathBuf>,
     /// Repository output directory
-    outdir: PathBuf,
+    #[structopt(
+        name = "outdir",
+        required_unless = "outdir-option",
+        conflicts_with = "outdir-option"
+    )]
+    outdir_positional: Option<PathBuf>,
+    /// Repository output directory, for use when `indir` is omitted
+    #[structopt(
+        short = "o",
+        long = "outdir",
+        name = "outdir-option",
+        value_name = "outdir"
+    )]
+    outdir_option: Option<PathBuf>,
 }
 
 impl CreateArgs {
@@ -96,8 +173,47 @@ impl CreateArgs {
         root_sha256.copy_from_slice(Sha256::digest(&root_buf).as_slice());
         let root_length = root_buf.len() as u64;
 
+        let custom = match &self.custom_manifest {
+            Some(path) => load_manifest(path)?,
+            None => HashMap::new(),
+        };
+        let custom_schema = match &self.custom_schema {
+            Some(path) => {
+                let schema: Value = crate::load_file(path)?;
+                match JSONSchema::compile(&schema) {
+                    Ok(schema) => Some(schema),
+                    Err(err) => {
+                        return error::CustomSchema {
+                            path,
+                            msg: err.to_string(),
+                        }
+                        .fail()
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let manifest_targets = match &self.targets_manifest {
+            Some(path) => load_targets_manifest(path)?,
+            None => HashMap::new(),
+        };
+
+        let hash_cache = match (&self.hash_cache_file, self.hash_cache) {
+            (Some(path), _) => Some(HashCache::load(path)?),
+            (None, true) => Some(HashCache::load(&default_hash_cache_file(self.outdir())?)?),
+            (None, false) => None,
+        };
+
         CreateProcess {
             args: self,
+            copy_action: self.copy_action()?,
+            indir: self.indir.clone(),
+            outdir: self.outdir().to_owned(),
+            hash_cache,
+            custom,
+            custom_schema,
+            manifest_targets,
             keys: keys_for_root(&self.keys, &root)?,
             rng: SystemRandom::new(),
             root,
@@ -108,8 +224,45 @@ impl CreateArgs {
     }
 }
 
+impl CreateArgs {
+    fn copy_action(&self) -> Result<Copylike> {
+        // --copy overrides --reflink, which overrides --hardlink, which overrides --auto
+        Ok(if self.copy {
+            Copylike::Copy
+        } else if self.reflink {
+            Copylike::Reflink
+        } else if self.hardlink {
+            Copylike::Hardlink
+        } else if self.auto {
+            // Without reflink support, skip trying (and failing) to clone each file.
+            let outdir = self.outdir();
+            if copylike::reflink_supported(outdir).context(error::ReflinkProbe { path: outdir })? {
+                Copylike::Reflink
+            } else {
+                Copylike::Copy
+            }
+        } else {
+            Copylike::Symlink
+        })
+    }
+
+    fn outdir(&self) -> &Path {
+        self.outdir_positional
+            .as_ref()
+            .or(self.outdir_option.as_ref())
+            .unwrap_or_else(|| unreachable!("clap requires one of outdir and --outdir"))
+    }
+}
+
 struct CreateProcess<'a> {
     args: &'a CreateArgs,
+    copy_action: Copylike,
+    indir: Option<PathBuf>,
+    outdir: PathBuf,
+    hash_cache: Option<HashCache>,
+    custom: HashMap<String, HashMap<String, Value>>,
+    custom_schema: Option<JSONSchema>,
+    manifest_targets: HashMap<String, PathBuf>,
     rng: SystemRandom,
     root: Root,
     root_sha256: [u8; 32],
@@ -120,24 +273,28 @@ struct CreateProcess<'a> {
 impl<'a> CreateProcess<'a> {
     fn run(self) -> Result<()> {
         let root_path = self
-            .args
             .outdir
             .join("metadata")
             .join(format!("{}.root.json", self.root.version));
-        self.copy_action()
+        self.copy_action
             .run(&self.args.root, &root_path)
             .context(error::FileCopy {
-                action: self.copy_action(),
+                action: self.copy_action,
                 src: &self.args.root,
                 dst: root_path,
             })?;
 
+        let targets = self.build_targets()?;
+        if let Some(hash_cache) = &self.hash_cache {
+            hash_cache.save()?;
+        }
+
         let (targets_sha256, targets_length) = self.write_metadata(
             Targets {
                 spec_version: crate::SPEC_VERSION.to_owned(),
                 version: self.args.targets_version,
                 expires: self.args.targets_expires,
-                targets: self.build_targets()?,
+                targets,
                 _extra: HashMap::new(),
             },
             self.args.targets_version,
@@ -202,72 +359,202 @@ impl<'a> CreateProcess<'a> {
 
     // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
-    fn copy_action(&self) -> Copylike {
-        match (self.args.copy, self.args.hardlink) {
-            (true, _) => Copylike::Copy, // --copy overrides --hardlink
-            (false, true) => Copylike::Hardlink,
-            (false, false) => Copylike::Symlink,
+    fn build_targets(&self) -> Result<HashMap<String, Target>> {
+        let mut targets = match &self.indir {
+            Some(indir) => WalkDir::new(indir)
+                .follow_links(self.args.follow)
+                .into_iter()
+                .par_bridge()
+                .filter_map(|entry| match entry {
+                    Ok(entry) => {
+                        if entry.file_type().is_file() && !self.is_sidecar(entry.path()) {
+                            Some(
+                                target_name(indir, entry.path())
+                                    .and_then(|name| self.process_target(entry.path(), name)),
+                            )
+                        } else {
+                            None
+                        }
+                    }
+                    Err(err) => Some(Err(err).context(error::WalkDir)),
+                })
+                .collect::<Result<HashMap<_, _>>>()?,
+            None => HashMap::new(),
+        };
+
+        for target in self.manifest_targets.keys() {
+            ensure!(
+                !targets.contains_key(target),
+                error::TargetDuplicate { target }
+            );
         }
+        let manifest_targets = self
+            .manifest_targets
+            .par_iter()
+            .map(|(name, path)| self.process_target(path, name.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        targets.extend(manifest_targets);
+
+        // A manifest entry that matches no target is most likely a typo.
+        for target in self.custom.keys() {
+            ensure!(
+                targets.contains_key(target),
+                error::CustomTargetMissing { target }
+            );
+        }
+        Ok(targets)
     }
 
-    fn build_targets(&self) -> Result<HashMap<String, Target>> {
-        WalkDir::new(&self.args.indir)
-            .follow_links(self.args.follow)
-            .into_iter()
-            .par_bridge()
-            .filter_map(|entry| match entry {
-                Ok(entry) => {
-                    if entry.file_type().is_file() {
-                        Some(self.process_target(entry.path()))
-                    } else {
-                        None
-                    }
-                }
-                Err(err) => Some(Err(err).context(error::WalkDir)),
-            })
-            .collect()
+    fn is_sidecar(&self, path: &Path) -> bool {
+        self.args.custom_sidecars && path.to_string_lossy().ends_with(SIDECAR_SUFFIX)
     }
 
-    fn process_target(&self, path: &Path) -> Result<(String, Target)> {
-        let target_name = path.strip_prefix(&self.args.indir).context(error::Prefix {
-            path,
-            base: &self.args.indir,
-        })?;
-        let target_name = target_name
-            .to_str()
-            .context(error::PathUtf8 { path: target_name })?
-            .to_owned();
+    /// Adds the custom metadata for a target from the manifest and its sidecar file to the
+    /// recorded file attributes in `custom`, and checks the result against the schema.
+    fn custom_metadata(
+        &self,
+        target_name: &str,
+        path: &Path,
+        mut custom: HashMap<String, Value>,
+    ) -> Result<HashMap<String, Value>> {
+        if let Some(manifest_custom) = self.custom.get(target_name) {
+            custom.extend(manifest_custom.clone());
+        }
+
+        if self.args.custom_sidecars {
+            let mut sidecar = OsString::from(path);
+            sidecar.push(SIDECAR_SUFFIX);
+            let sidecar = PathBuf::from(sidecar);
+            if sidecar.is_file() {
+                let sidecar_custom: HashMap<String, Value> = crate::load_file(&sidecar)?;
+                custom.extend(sidecar_custom);
+            }
+        }
+
+        if let Some(schema) = &self.custom_schema {
+            let instance = Value::Object(custom.clone().into_iter().collect());
+            let errors = match schema.validate(&instance) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors
+                    .map(|err| {
+                        let pointer = err.instance_path.to_string();
+                        if pointer.is_empty() {
+                            err.to_string()
+                        } else {
+                            format!("{}: {}", pointer, err)
+                        }
+                    })
+                    .collect(),
+            };
+            ensure!(
+                errors.is_empty(),
+                error::CustomInvalid {
+                    target: target_name,
+                    errors: errors.join("; "),
+                }
+            );
+        }
+        Ok(custom)
+    }
 
+    fn process_target(&self, path: &Path, target_name: String) -> Result<(String, Target)> {
         let mut file = File::open(path).context(error::FileOpen { path })?;
-        let mut digest = Sha256::new();
-        let length = std::io::copy(&mut file, &mut digest).context(error::FileRead { path })?;
+        let metadata = file.metadata().context(error::FileRead { path })?;
+        let cache_key = match &self.hash_cache {
+            Some(_) => Some(std::fs::canonicalize(path).context(error::FileOpen { path })?),
+            None => None,
+        };
+        let cached = match (&self.hash_cache, &cache_key) {
+            (Some(hash_cache), Some(cache_key)) => hash_cache.get(cache_key, &metadata),
+            _ => None,
+        };
+
+        // The file has to be read anyway when searching it for a version string.
+        let mut scanner = self.args.record_version.as_ref().map(VersionScanner::new);
+        let (length, sha256) = match cached.clone() {
+            Some(sha256) if scanner.is_none() && !self.args.verify_cache => {
+                (metadata.len(), sha256)
+            }
+            _ => {
+                let mut digest = Sha256::new();
+                let mut length = 0;
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    let n = file.read(&mut buf).context(error::FileRead { path })?;
+                    if n == 0 {
+                        break;
+                    }
+                    digest.input(&buf[..n]);
+                    if let Some(scanner) = &mut scanner {
+                        scanner.feed(&buf[..n]);
+                    }
+                    length += n as u64;
+                }
+                let sha256 = Decoded::from(digest.result().as_slice().to_vec());
+                if let Some(cached) = cached {
+                    if cached != sha256 {
+                        eprintln!("Hash cache entry for {} was stale", path.display());
+                    }
+                }
+                (length, sha256)
+            }
+        };
+        if let (Some(hash_cache), Some(cache_key)) = (&self.hash_cache, &cache_key) {
+            hash_cache.insert(cache_key, &metadata, sha256.clone());
+        }
+
+        let mut custom = HashMap::new();
+        if self.args.record_permissions {
+            custom.insert(
+                "file_permissions".to_owned(),
+                Value::String(file_permissions(&metadata)),
+            );
+        }
+        if self.args.record_mtime {
+            let modified = metadata.modified().context(error::FileRead { path })?;
+            custom.insert(
+                "modified".to_owned(),
+                Value::String(DateTime::<Utc>::from(modified).to_rfc3339()),
+            );
+        }
+        if self.args.record_media_type {
+            custom.insert(
+                "media_type".to_owned(),
+                Value::String(
+                    mime_guess::from_path(&target_name)
+                        .first_or_octet_stream()
+                        .to_string(),
+                ),
+            );
+        }
+        if let Some(version) = scanner.and_then(VersionScanner::finish) {
+            custom.insert("version".to_owned(), Value::String(version));
+        }
 
         let target = Target {
             length,
             hashes: Hashes {
-                sha256: Decoded::from(digest.result().as_slice().to_vec()),
+                sha256,
                 _extra: HashMap::new(),
             },
-            custom: HashMap::new(),
+            custom: self.custom_metadata(&target_name, path, custom)?,
             _extra: HashMap::new(),
         };
 
         let dst = if self.root.consistent_snapshot {
-            self.args.outdir.join("targets").join(format!(
+            self.outdir.join("targets").join(format!(
                 "{}.{}",
                 hex::encode(&target.hashes.sha256),
                 target_name
             ))
         } else {
-            self.args.outdir.join("targets").join(&target_name)
+            self.outdir.join("targets").join(&target_name)
         };
-        self.copy_action()
-            .run(path, &dst)
-            .context(error::FileCopy {
-                action: self.copy_action(),
-                src: path,
-                dst,
-            })?;
+        self.copy_action.run(path, &dst).context(error::FileCopy {
+            action: self.copy_action,
+            src: path,
+            dst,
+        })?;
 
         Ok((target_name, target))
     }
@@ -280,7 +567,7 @@ impl<'a> CreateProcess<'a> {
         version: NonZeroU64,
         filename: &'static str,
     ) -> Result<([u8; 32], u64)> {
-        let metadir = self.args.outdir.join("metadata");
+        let metadir = self.outdir.join("metadata");
         std::fs::create_dir_all(&metadir).context(error::FileCreate { path: &metadir })?;
 
         let path = metadir.join(
@@ -311,3 +598,265 @@ impl<'a> CreateProcess<'a> {
         sign_metadata(&self.root, &self.keys, role, &self.rng)
     }
 }
+
+/// Formats a file's permission bits in octal, as the TUF reference implementation does (e.g.
+/// "0644").
+fn file_permissions(metadata: &Metadata) -> String {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o7777
+    };
+
+    // Other platforms only expose the read-only flag.
+    #[cfg(not(unix))]
+    let mode = if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    };
+
+    format!("{:04o}", mode)
+}
+
+/// Searches a stream of data for a version string, keeping enough of the previous chunk that
+/// matches spanning two chunks are found.
+struct VersionScanner<'a> {
+    pattern: &'a Regex,
+    window: Vec<u8>,
+    found: Option<String>,
+}
+
+impl<'a> VersionScanner<'a> {
+    const OVERLAP: usize = 4096;
+
+    fn new(pattern: &'a Regex) -> Self {
+        Self {
+            pattern,
+            window: Vec::new(),
+            found: None,
+        }
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        if self.found.is_some() {
+            return;
+        }
+        self.window.extend_from_slice(data);
+        // A match that reaches the end of the window might continue in the next chunk.
+        self.search(false);
+        if self.found.is_none() && self.window.len() > Self::OVERLAP {
+            self.window.drain(..self.window.len() - Self::OVERLAP);
+        }
+    }
+
+    fn finish(mut self) -> Option<String> {
+        if self.found.is_none() {
+            self.search(true);
+        }
+        self.found
+    }
+
+    fn search(&mut self, at_end: bool) {
+        if let Some(captures) = self.pattern.captures(&self.window) {
+            let whole = captures.get(0).map_or(0, |m| m.end());
+            if at_end || whole < self.window.len() {
+                let found = captures.get(1).or_else(|| captures.get(0));
+                self.found = found.map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned());
+            }
+        }
+    }
+}
+
+/// Places the hash cache next to `outdir` rather than in it, where it would be published.
+fn default_hash_cache_file(outdir: &Path) -> Result<PathBuf> {
+    let name = outdir
+        .file_name()
+        .context(error::HashCacheDefault { outdir })?;
+    let mut file_name = OsString::from(".");
+    file_name.push(name);
+    file_name.push(".tuftool-hash-cache.json");
+    Ok(outdir.with_file_name(file_name))
+}
+
+/// Derives a target's name from its path within `indir`.
+fn target_name(indir: &Path, path: &Path) -> Result<String> {
+    let target_name = path
+        .strip_prefix(indir)
+        .context(error::Prefix { path, base: indir })?;
+    Ok(target_name
+        .to_str()
+        .context(error::PathUtf8 { path: target_name })?
+        .to_owned())
+}
+
+/// Loads a map of target names to source files. Source paths are resolved relative to the
+/// manifest, and canonicalized so that symlinks into `outdir` point at them correctly.
+fn load_targets_manifest(path: &Path) -> Result<HashMap<String, PathBuf>> {
+    let base = path.parent().context(error::PathParent { path })?;
+    let manifest: HashMap<String, PathBuf> = load_manifest(path)?;
+    manifest
+        .into_iter()
+        .map(|(name, source)| {
+            // Target names become paths under `outdir`; refuse any that would escape it.
+            ensure!(
+                Path::new(&name)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_))),
+                error::TargetNameUnsafe { target: name }
+            );
+            let source = base.join(source);
+            let source =
+                std::fs::canonicalize(&source).context(error::FileOpen { path: source })?;
+            Ok((name, source))
+        })
+        .collect()
+}
+
+/// Loads a manifest file, as TOML if the file name ends in `.toml` and JSON otherwise.
+fn load_manifest<T>(path: &Path) -> Result<T>
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    if path.extension() == Some(OsStr::new("toml")) {
+        let buf = std::fs::read_to_string(path).context(error::FileRead { path })?;
+        toml::from_str(&buf).context(error::FileParseToml { path })
+    } else {
+        crate::load_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_hash_cache_file, CreateArgs, CreateProcess, VersionScanner};
+    use regex::bytes::Regex;
+    use ring::rand::SystemRandom;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use structopt::StructOpt;
+    use tempfile::TempDir;
+    use tough::schema::{Root, Signed};
+
+    fn parse(args: &[&str]) -> Result<CreateArgs, structopt::clap::Error> {
+        let required = [
+            "create",
+            "--root=root.json",
+            "--snapshot-version=1",
+            "--snapshot-expires=2030-01-01T00:00:00Z",
+            "--targets-version=1",
+            "--targets-expires=2030-01-01T00:00:00Z",
+            "--timestamp-version=1",
+            "--timestamp-expires=2030-01-01T00:00:00Z",
+        ];
+        CreateArgs::from_iter_safe(required.iter().chain(args))
+    }
+
+    #[test]
+    fn directory_arguments() {
+        let args = parse(&["in", "out"]).unwrap();
+        assert_eq!(args.indir.as_deref(), Some(Path::new("in")));
+        assert_eq!(args.outdir(), Path::new("out"));
+
+        let args = parse(&["--targets-manifest=targets.toml", "in", "out"]).unwrap();
+        assert_eq!(args.indir.as_deref(), Some(Path::new("in")));
+        assert_eq!(args.outdir(), Path::new("out"));
+
+        let args = parse(&["--targets-manifest=targets.toml", "--outdir=out"]).unwrap();
+        assert_eq!(args.indir, None);
+        assert_eq!(args.outdir(), Path::new("out"));
+
+        let args = parse(&["--targets-manifest=targets.toml", "in", "-o", "out"]).unwrap();
+        assert_eq!(args.indir.as_deref(), Some(Path::new("in")));
+        assert_eq!(args.outdir(), Path::new("out"));
+
+        // A lone positional argument is always `indir`, so the output directory is missing.
+        assert!(parse(&["out"]).is_err());
+        assert!(parse(&["--targets-manifest=targets.toml", "out"]).is_err());
+        // `indir` is required without --targets-manifest, and there can only be one `outdir`.
+        assert!(parse(&["--outdir=out"]).is_err());
+        assert!(parse(&["in", "out", "--outdir=out"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn hash_cache_outside_outdir() {
+        assert_eq!(
+            default_hash_cache_file(Path::new("repo/out")).unwrap(),
+            Path::new("repo/.out.tuftool-hash-cache.json")
+        );
+        assert_eq!(
+            default_hash_cache_file(Path::new("out/")).unwrap(),
+            Path::new(".out.tuftool-hash-cache.json")
+        );
+        assert!(default_hash_cache_file(Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn version_across_chunks() {
+        let pattern = Regex::new(r"VERSION=([0-9.]+)").unwrap();
+        let mut scanner = VersionScanner::new(&pattern);
+        scanner.feed(&[0; 10000]);
+        scanner.feed(b"xxVERSION=1.");
+        scanner.feed(b"22.3\0");
+        scanner.feed(b"VERSION=9.9\0");
+        assert_eq!(scanner.finish(), Some("1.22.3".to_owned()));
+
+        let mut scanner = VersionScanner::new(&pattern);
+        scanner.feed(b"VERSION=2.0");
+        assert_eq!(scanner.finish(), Some("2.0".to_owned()));
+
+        let mut scanner = VersionScanner::new(&pattern);
+        scanner.feed(b"no version here");
+        assert_eq!(scanner.finish(), None);
+    }
+
+    #[test]
+    fn custom_attributes() {
+        let indir = TempDir::new().unwrap();
+        let outdir = TempDir::new().unwrap();
+        let path = indir.path().join("app.bin");
+        std::fs::write(&path, b"\0\0APP_VERSION=1.2.3\0\0").unwrap();
+
+        let args = parse(&[
+            "--copy",
+            "--record-permissions",
+            "--record-mtime",
+            "--record-media-type",
+            "--record-version=APP_VERSION=([0-9.]+)",
+            indir.path().to_str().unwrap(),
+            outdir.path().to_str().unwrap(),
+        ])
+        .unwrap();
+        let root: Signed<Root> = crate::load_file(Path::new(
+            "../tough/tests/data/tuf-reference-impl/metadata/1.root.json",
+        ))
+        .unwrap();
+        let process = CreateProcess {
+            args: &args,
+            copy_action: args.copy_action().unwrap(),
+            indir: args.indir.clone(),
+            outdir: outdir.path().to_owned(),
+            hash_cache: None,
+            custom: HashMap::new(),
+            custom_schema: None,
+            manifest_targets: HashMap::new(),
+            rng: SystemRandom::new(),
+            root: root.signed,
+            root_sha256: [0; 32],
+            root_length: 0,
+            keys: HashMap::new(),
+        };
+
+        let (name, target) = process.process_target(&path, "app.bin".to_owned()).unwrap();
+        assert_eq!(name, "app.bin");
+        assert_eq!(target.custom["version"], Value::from("1.2.3"));
+        assert!(!target.custom.contains_key("version_signature"));
+        assert_eq!(
+            target.custom["media_type"],
+            Value::from("application/octet-stream")
+        );
+        assert!(target.custom["file_permissions"].is_string());
+        assert!(target.custom["modified"].is_string());
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Computes a human-reviewable list of changes between two versions of root.json.
+
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+use tough::schema::key::Key;
+use tough::schema::{RoleKeys, RoleType, Root};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Change {
+    Added(String),
+    Removed(String),
+    Modified {
+        what: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(what) => write!(f, "+ {}", what),
+            Change::Removed(what) => write!(f, "- {}", what),
+            Change::Modified { what, old, new } => write!(f, "~ {}: {} -> {}", what, old, new),
+        }
+    }
+}
+
+/// Lists every change from `old` to `new`.
+pub(crate) fn diff_roots(old: &Root, new: &Root) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    modified(
+        &mut changes,
+        "spec_version",
+        &old.spec_version,
+        &new.spec_version,
+    );
+    modified(&mut changes, "version", &old.version, &new.version);
+    modified(
+        &mut changes,
+        "expires",
+        &old.expires.to_rfc3339(),
+        &new.expires.to_rfc3339(),
+    );
+    modified(
+        &mut changes,
+        "consistent_snapshot",
+        &old.consistent_snapshot,
+        &new.consistent_snapshot,
+    );
+
+    let old_keys = old.keys.keys().map(hex::encode).collect::<BTreeSet<_>>();
+    let new_keys = new.keys.keys().map(hex::encode).collect::<BTreeSet<_>>();
+    for (key_id, key) in &old.keys {
+        let key_id = hex::encode(key_id);
+        if !new_keys.contains(&key_id) {
+            changes.push(Change::Removed(format!(
+                "key {} ({})",
+                key_id,
+                key_field(key, "keytype")
+            )));
+        }
+    }
+    for (key_id, key) in &new.keys {
+        let key_id = hex::encode(key_id);
+        if !old_keys.contains(&key_id) {
+            changes.push(Change::Added(format!(
+                "key {} ({})",
+                key_id,
+                key_field(key, "keytype")
+            )));
+        }
+    }
+
+    let mut roles = old.roles.keys().chain(new.roles.keys()).collect::<Vec<_>>();
+    roles.sort_by_key(ToString::to_string);
+    roles.dedup();
+    for role in roles {
+        diff_role(
+            &mut changes,
+            *role,
+            old.roles.get(role),
+            new.roles.get(role),
+        );
+    }
+
+    diff_extra(&mut changes, "_extra", &old._extra, &new._extra);
+
+    // Changes are listed in a stable order so that independent reviewers see identical output.
+    changes.sort_by_key(sort_key);
+    changes
+}
+
+fn diff_role(
+    changes: &mut Vec<Change>,
+    role: RoleType,
+    old: Option<&RoleKeys>,
+    new: Option<&RoleKeys>,
+) {
+    match (old, new) {
+        (None, None) => {}
+        (Some(_), None) => changes.push(Change::Removed(format!("role {}", role))),
+        (None, Some(new)) => {
+            changes.push(Change::Added(format!(
+                "role {} (threshold {})",
+                role, new.threshold
+            )));
+            for key_id in &new.keyids {
+                changes.push(Change::Added(format!(
+                    "role {} key {}",
+                    role,
+                    hex::encode(key_id)
+                )));
+            }
+        }
+        (Some(old), Some(new)) => {
+            modified(
+                changes,
+                &format!("role {} threshold", role),
+                &old.threshold,
+                &new.threshold,
+            );
+            for key_id in &old.keyids {
+                if !new.keyids.contains(key_id) {
+                    changes.push(Change::Removed(format!(
+                        "role {} key {}",
+                        role,
+                        hex::encode(key_id)
+                    )));
+                }
+            }
+            for key_id in &new.keyids {
+                if !old.keyids.contains(key_id) {
+                    changes.push(Change::Added(format!(
+                        "role {} key {}",
+                        role,
+                        hex::encode(key_id)
+                    )));
+                }
+            }
+            diff_extra(
+                changes,
+                &format!("role {} _extra", role),
+                &old._extra,
+                &new._extra,
+            );
+        }
+    }
+}
+
+fn diff_extra(
+    changes: &mut Vec<Change>,
+    prefix: &str,
+    old: &HashMap<String, Value>,
+    new: &HashMap<String, Value>,
+) {
+    for (key, old_value) in old {
+        match new.get(key) {
+            None => changes.push(Change::Removed(format!(
+                "{}.{} = {}",
+                prefix, key, old_value
+            ))),
+            Some(new_value) => modified(
+                changes,
+                &format!("{}.{}", prefix, key),
+                old_value,
+                new_value,
+            ),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(Change::Added(format!("{}.{} = {}", prefix, key, new_value)));
+        }
+    }
+}
+
+fn modified<T: Display + PartialEq + ?Sized>(
+    changes: &mut Vec<Change>,
+    what: &str,
+    old: &T,
+    new: &T,
+) {
+    if old != new {
+        changes.push(Change::Modified {
+            what: what.to_owned(),
+            old: old.to_string(),
+            new: new.to_string(),
+        });
+    }
+}
+
+fn sort_key(change: &Change) -> (String, u8) {
+    match change {
+        Change::Modified { what, .. } => (what.clone(), 0),
+        Change::Removed(what) => (what.clone(), 1),
+        Change::Added(what) => (what.clone(), 2),
+    }
+}
+
+/// Returns a string field of a key, such as `keytype` or `scheme`, as it appears in root.json.
+pub(crate) fn key_field(key: &Key, field: &str) -> String {
+    serde_json::to_value(key)
+        .ok()
+        .and_then(|value| value.get(field).and_then(Value::as_str).map(str::to_owned))
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_roots, Change};
+    use std::num::NonZeroU64;
+    use tough::schema::{RoleType, Root, Signed};
+
+    fn root() -> Root {
+        serde_json::from_str::<Signed<Root>>(include_str!(
+            "../../tough/tests/data/simple-rsa/root.json"
+        ))
+        .unwrap()
+        .signed
+    }
+
+    #[test]
+    fn identical() {
+        assert!(diff_roots(&root(), &root()).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = root();
+        let mut new = root();
+        new.version = NonZeroU64::new(old.version.get() + 1).unwrap();
+        new.consistent_snapshot = !old.consistent_snapshot;
+        let targets = new.roles.get_mut(&RoleType::Targets).unwrap();
+        targets.threshold = NonZeroU64::new(2).unwrap();
+        let removed = targets.keyids.remove(0);
+        new._extra
+            .insert("foo".to_owned(), serde_json::json!({"bar": 1}));
+
+        let changes = diff_roots(&old, &new);
+        assert_eq!(changes.len(), 5, "{:?}", changes);
+        assert!(changes.contains(&Change::Modified {
+            what: "version".to_owned(),
+            old: old.version.to_string(),
+            new: new.version.to_string(),
+        }));
+        assert!(changes.contains(&Change::Modified {
+            what: "consistent_snapshot".to_owned(),
+            old: old.consistent_snapshot.to_string(),
+            new: new.consistent_snapshot.to_string(),
+        }));
+        assert!(changes.contains(&Change::Modified {
+            what: "role targets threshold".to_owned(),
+            old: "1".to_owned(),
+            new: "2".to_owned(),
+        }));
+        assert!(changes.contains(&Change::Removed(format!(
+            "role targets key {}",
+            hex::encode(&removed)
+        ))));
+        assert!(changes.contains(&Change::Added(r#"_extra.foo = {"bar":1}"#.to_owned())));
+    }
+}
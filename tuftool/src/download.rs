@@ -2,14 +2,18 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::error::{self, Result};
-use snafu::{OptionExt, ResultExt};
+use glob::Pattern;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
 use std::fs::{File, OpenOptions};
 use std::io::{self};
-use std::num::NonZeroU64;
-use std::path::{Path, PathBuf};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Component, Path, PathBuf};
 use structopt::StructOpt;
 use tempdir::TempDir;
-use tough::{HttpTransport, Limits, Repository, Settings};
+use tempfile::NamedTempFile;
+use tough::{Limits, Repository, SchemeTransport, Settings, Target, Transport};
 use url::Url;
 
 #[derive(Debug, StructOpt)]
@@ -34,6 +38,18 @@ pub(crate) struct DownloadArgs {
     #[structopt(long)]
     allow_root_download: bool,
 
+    /// Download only this target (may be given multiple times)
+    #[structopt(long = "target", number_of_values = 1)]
+    targets: Vec<String>,
+
+    /// Download only targets whose names match this glob pattern (may be given multiple times)
+    #[structopt(long = "filter", number_of_values = 1)]
+    filters: Vec<Pattern>,
+
+    /// Number of targets to download at once (default: number of cores)
+    #[structopt(short = "j", long = "jobs")]
+    jobs: Option<NonZeroUsize>,
+
     /// Output directory of targets
     indir: PathBuf,
 }
@@ -50,6 +66,15 @@ This is unsafe and will not establish trust, use only for testing
 
 impl DownloadArgs {
     pub(crate) fn run(&self) -> Result<()> {
+        if let Some(jobs) = self.jobs {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(usize::from(jobs))
+                .build_global()
+                .context(error::InitializeThreadPool)?;
+        }
+
+        let transport = SchemeTransport::new();
+
         // use local root.json or download from repository
         let root_path = if let Some(path) = &self.root {
             PathBuf::from(path)
@@ -78,10 +103,10 @@ impl DownloadArgs {
                 .create(true)
                 .open(&path)
                 .context(error::OpenFile { path: &path })?;
-            reqwest::get(url.as_str())
-                .context(error::ReqwestGet)?
-                .copy_to(&mut f)
-                .context(error::ReqwestCopy)?;
+            let mut reader = transport
+                .fetch(url.clone())
+                .context(error::Transport { url: url.as_str() })?;
+            io::copy(&mut reader, &mut f).context(error::FileWrite { path: &path })?;
             path
         } else {
             eprintln!("No root.json available");
@@ -89,7 +114,6 @@ impl DownloadArgs {
         };
 
         // load repository
-        let transport = HttpTransport::new();
         let repo_dir = TempDir::new("tuf").context(error::TempDir)?;
         let repository = Repository::load(
             &transport,
@@ -101,26 +125,188 @@ impl DownloadArgs {
                 limits: Limits {
                     ..tough::Limits::default()
                 },
+                observer: None,
             },
         )
         .context(error::Metadata)?;
 
-        // copy all available targets
+        for target in &self.targets {
+            ensure!(
+                repository.targets().contains_key(target),
+                error::TargetNotFound { target }
+            );
+        }
+
+        // copy the selected targets
         println!("Downloading targets to {:?}", &self.indir);
-        for target in repository.targets().keys() {
-            let path = PathBuf::from(&self.indir).join(target);
-            println!("\t-> {}", &target);
-            let mut reader = repository
-                .read_target(target)
-                .context(error::Metadata)?
-                .context(error::TargetNotFound { target })?;
-            let mut f = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(&path)
-                .context(error::OpenFile { path: &path })?;
-            io::copy(&mut reader, &mut f).context(error::WriteTarget)?;
+        repository
+            .targets()
+            .par_iter()
+            .filter(|(name, _)| self.selected(name))
+            .try_for_each(|(name, target)| download_target(&repository, &self.indir, name, target))
+    }
+
+    /// Returns whether a target was chosen with `--target` or `--filter`. With neither option,
+    /// every target is selected.
+    fn selected(&self, name: &str) -> bool {
+        (self.targets.is_empty() && self.filters.is_empty())
+            || self.targets.iter().any(|target| target == name)
+            || self.filters.iter().any(|filter| filter.matches(name))
+    }
+}
+
+fn download_target<T: Transport + Sync>(
+    repository: &Repository<'_, T>,
+    indir: &Path,
+    name: &str,
+    target: &Target,
+) -> Result<()> {
+    // Target names become paths under `indir`; refuse any that would escape it.
+    let relative = Path::new(name);
+    ensure!(
+        relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_))),
+        error::TargetNameUnsafe { target: name }
+    );
+    let path = indir.join(relative);
+    if up_to_date(&path, target)? {
+        println!("\t=  {} (up to date)", name);
+        return Ok(());
+    }
+
+    let parent = path.parent().context(error::PathParent { path: &path })?;
+    std::fs::create_dir_all(parent).context(error::FileCreate { path: parent })?;
+    let mut reader = repository
+        .read_target(name)
+        .context(error::Metadata)?
+        .context(error::TargetNotFound { target: name })?;
+    // The reader fails if the data does not match the target's hash, so write it to a temporary
+    // file first and only move it into place once it has been fully read.
+    let mut f = NamedTempFile::new_in(parent).context(error::FileTempCreate { path: parent })?;
+    io::copy(&mut reader, &mut f).context(error::WriteTarget)?;
+    f.persist(&path)
+        .context(error::FilePersist { path: &path })?;
+    println!("\t-> {}", name);
+    Ok(())
+}
+
+/// Returns whether the file at `path` already has the target's contents.
+fn up_to_date(path: &Path, target: &Target) -> Result<bool> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).context(error::FileOpen { path }),
+    };
+    let length = file.metadata().context(error::FileRead { path })?.len();
+    // A file of any other length can't match, so don't spend time hashing it.
+    if length != target.length {
+        return Ok(false);
+    }
+    let mut digest = Sha256::new();
+    io::copy(&mut file, &mut digest).context(error::FileRead { path })?;
+    Ok(digest.result().as_slice() == target.sha256.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{up_to_date, DownloadArgs};
+    use glob::Pattern;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+    use url::Url;
+
+    fn reference_repo() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tough/tests/data/tuf-reference-impl")
+    }
+
+    fn download(indir: &Path, targets: &[&str], filters: &[&str]) {
+        let base = reference_repo().canonicalize().unwrap();
+        let dir_url = |dir: &str| {
+            Url::from_directory_path(base.join(dir))
+                .unwrap()
+                .to_string()
+        };
+        DownloadArgs {
+            root: Some(base.join("metadata").join("1.root.json")),
+            root_version: None,
+            metadata_base_url: dir_url("metadata"),
+            target_base_url: dir_url("targets"),
+            allow_root_download: false,
+            targets: targets.iter().map(|&target| target.to_owned()).collect(),
+            filters: filters
+                .iter()
+                .map(|filter| Pattern::new(filter).unwrap())
+                .collect(),
+            jobs: None,
+            indir: indir.to_owned(),
+        }
+        .run()
+        .unwrap();
+    }
+
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn selection() {
+        let indir = TempDir::new().unwrap();
+        download(indir.path(), &["file1.txt"], &[]);
+        assert_eq!(listing(indir.path()), ["file1.txt"]);
+
+        let indir = TempDir::new().unwrap();
+        download(indir.path(), &[], &["*2.txt"]);
+        assert_eq!(listing(indir.path()), ["file2.txt"]);
+
+        // With no selection, every target is downloaded (in parallel).
+        let indir = TempDir::new().unwrap();
+        download(indir.path(), &[], &[]);
+        assert_eq!(listing(indir.path()), ["file1.txt", "file2.txt"]);
+        for name in listing(indir.path()) {
+            assert_eq!(
+                std::fs::read(indir.path().join(&name)).unwrap(),
+                std::fs::read(reference_repo().join("targets").join(&name)).unwrap()
+            );
         }
-        Ok(())
+    }
+
+    #[test]
+    fn resume() {
+        let indir = TempDir::new().unwrap();
+        let expected = std::fs::read(reference_repo().join("targets/file1.txt")).unwrap();
+        let path = indir.path().join("file1.txt");
+
+        // Missing, truncated, and modified files are downloaded again.
+        let targets = {
+            let base = reference_repo();
+            let targets: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(base.join("metadata/targets.json")).unwrap())
+                    .unwrap();
+            let target = &targets["signed"]["targets"]["file1.txt"];
+            tough::Target {
+                custom: std::collections::HashMap::new(),
+                sha256: hex::decode(target["hashes"]["sha256"].as_str().unwrap()).unwrap(),
+                length: target["length"].as_u64().unwrap(),
+            }
+        };
+        assert!(!up_to_date(&path, &targets).unwrap());
+        std::fs::write(&path, &expected[..10]).unwrap();
+        assert!(!up_to_date(&path, &targets).unwrap());
+        download(indir.path(), &["file1.txt"], &[]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        assert!(up_to_date(&path, &targets).unwrap());
+
+        let mut modified = expected.clone();
+        modified[0] ^= 1;
+        std::fs::write(&path, &modified).unwrap();
+        assert!(!up_to_date(&path, &targets).unwrap());
+        download(indir.path(), &["file1.txt"], &[]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
     }
 }
@@ -4,6 +4,7 @@
 #![allow(clippy::default_trait_access)]
 
 use snafu::{Backtrace, Snafu};
+use std::num::NonZeroU64;
 use std::path::PathBuf;
 
 #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
@@ -41,6 +42,26 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Custom metadata for target {} is invalid: {}", target, errors))]
+    CustomInvalid {
+        target: String,
+        errors: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Invalid JSON Schema {}: {}", path.display(), msg))]
+    CustomSchema {
+        path: PathBuf,
+        msg: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Custom metadata given for target {}, which does not exist", target))]
+    CustomTargetMissing {
+        target: String,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Date argument '{}' is invalid: {}", input, msg))]
     DateArgInvalid { input: String, msg: &'static str },
 
@@ -84,6 +105,13 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to parse {}: {}", path.display(), source))]
+    FileParseToml {
+        path: PathBuf,
+        source: toml::de::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to copy {} to {}: {}", source.file.path().display(), path.display(), source.error))]
     FilePersist {
         path: PathBuf,
@@ -98,6 +126,13 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to remove {}: {}", path.display(), source))]
+    FileRemove {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to create temporary file in {}: {}", path.display(), source))]
     FileTempCreate {
         path: PathBuf,
@@ -119,6 +154,15 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display(
+        "Cannot place the hash cache next to {}; use --hash-cache-file",
+        outdir.display()
+    ))]
+    HashCacheDefault {
+        outdir: PathBuf,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to initialize global thread pool: {}", source))]
     InitializeThreadPool {
         source: rayon::ThreadPoolBuildError,
@@ -139,6 +183,27 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to serialize key as JSON: {}", source))]
+    KeyExportJson {
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Key {} has an unrecognized type or scheme; it can only be exported as tuf-json",
+        key_id
+    ))]
+    KeyExportUnsupported {
+        key_id: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Key {} is not listed in root.json", key_id))]
+    KeyMissing {
+        key_id: String,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to calculate key ID: {}", source))]
     KeyId {
         #[snafu(backtrace)]
@@ -160,6 +225,15 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("No metadata file found matching {}", path.display()))]
+    MetadataMissing { path: PathBuf, backtrace: Backtrace },
+
+    #[snafu(display("None of the given keys are listed for the {} role", role))]
+    NoSigningKeys {
+        role: tough::schema::RoleType,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to open file {}: {}", path.display(), source))]
     OpenFile {
         path: PathBuf,
@@ -174,6 +248,9 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("{} is not in canonical JSON form", path.display()))]
+    PayloadNotCanonical { path: PathBuf, backtrace: Backtrace },
+
     #[snafu(display("Path {} is not valid UTF-8", path.display()))]
     PathUtf8 { path: PathBuf, backtrace: Backtrace },
 
@@ -189,15 +266,25 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
-    #[snafu(display("Failed to copy from response: {}", source))]
-    ReqwestCopy {
-        source: reqwest::Error,
+    #[snafu(display(
+        "Refusing to prune {}: the latest root metadata does not enable consistent snapshots",
+        path.display()
+    ))]
+    PruneNotConsistent { path: PathBuf, backtrace: Backtrace },
+
+    #[snafu(display("Refusing to prune {}: no versioned snapshot metadata found", path.display()))]
+    PruneNoSnapshots { path: PathBuf, backtrace: Backtrace },
+
+    #[snafu(display("Failed to check for reflink support in {}: {}", path.display(), source))]
+    ReflinkProbe {
+        path: PathBuf,
+        source: std::io::Error,
         backtrace: Backtrace,
     },
 
-    #[snafu(display("Error making request: {}", source))]
-    ReqwestGet {
-        source: reqwest::Error,
+    #[snafu(display("Refreshing the {} role is not supported", role))]
+    RefreshRole {
+        role: tough::schema::RoleType,
         backtrace: Backtrace,
     },
 
@@ -223,6 +310,101 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Role {} missing from root metadata", role))]
+    RoleMissing {
+        role: tough::schema::RoleType,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("New root would be rejected by clients trusting the old root"))]
+    RotationRejected { backtrace: Backtrace },
+
+    #[snafu(display("--rsa-scheme can only be used with RSA keys"))]
+    RsaSchemeNotRsa { backtrace: Backtrace },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display("Failed to complete upload of s3://{}/{}: {}", bucket, key, source))]
+    S3CompleteMultipartUpload {
+        bucket: String,
+        key: String,
+        source: rusoto_core::RusotoError<rusoto_s3::CompleteMultipartUploadError>,
+        backtrace: Backtrace,
+    },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display("Failed to start upload of s3://{}/{}: {}", bucket, key, source))]
+    S3CreateMultipartUpload {
+        bucket: String,
+        key: String,
+        source: rusoto_core::RusotoError<rusoto_s3::CreateMultipartUploadError>,
+        backtrace: Backtrace,
+    },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display("Failed to check s3://{}/{}: {}", bucket, key, source))]
+    S3HeadObject {
+        bucket: String,
+        key: String,
+        source: rusoto_core::RusotoError<rusoto_s3::HeadObjectError>,
+        backtrace: Backtrace,
+    },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display("Failed to upload s3://{}/{}: {}", bucket, key, source))]
+    S3PutObject {
+        bucket: String,
+        key: String,
+        source: rusoto_core::RusotoError<rusoto_s3::PutObjectError>,
+        backtrace: Backtrace,
+    },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display("No upload ID returned for s3://{}/{}", bucket, key))]
+    S3UploadIdMissing {
+        bucket: String,
+        key: String,
+        backtrace: Backtrace,
+    },
+
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    #[snafu(display(
+        "Failed to upload part {} of s3://{}/{}: {}",
+        part_number,
+        bucket,
+        key,
+        source
+    ))]
+    S3UploadPart {
+        bucket: String,
+        key: String,
+        part_number: i64,
+        source: rusoto_core::RusotoError<rusoto_s3::UploadPartError>,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Signature by key {} in {} does not verify", keyid, path.display()))]
+    SignatureInvalid {
+        keyid: String,
+        path: PathBuf,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Signature in {} is by key {}, which is not a {} key",
+        path.display(),
+        keyid,
+        role
+    ))]
+    SignatureKeyUnknown {
+        keyid: String,
+        path: PathBuf,
+        role: tough::schema::RoleType,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Private key does not match the key type or scheme listed in root.json"))]
+    SchemeMismatch { backtrace: Backtrace },
+
     #[snafu(display("Failed to sign message"))]
     Sign {
         source: ring::error::Unspecified,
@@ -270,6 +452,18 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Target {} is listed more than once", target))]
+    TargetDuplicate {
+        target: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Target name {} is not a safe relative path", target))]
+    TargetNameUnsafe {
+        target: String,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Target not found: {}", target))]
     TargetNotFound {
         target: String,
@@ -282,6 +476,13 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to fetch {}: {}", url, source))]
+    Transport {
+        url: String,
+        source: tough::SchemeTransportError,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Unrecognized or invalid public key"))]
     UnrecognizedKey { backtrace: Backtrace },
 
@@ -291,6 +492,9 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Cannot convert {} to a URL", path.display()))]
+    UrlFromPath { path: PathBuf, backtrace: Backtrace },
+
     #[snafu(display("Failed to parse URL \"{}\": {}", url, source))]
     UrlParse {
         url: String,
@@ -298,6 +502,27 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Repository verification failed with {} problem(s)", failures))]
+    VerifyFailed { failures: usize },
+
+    #[snafu(display("Signed {} metadata does not verify: {}", role, source))]
+    VerifySigned {
+        role: tough::schema::RoleType,
+        source: tough::schema::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Version {} is not newer than the current version {}; clients would reject it as a rollback",
+        version,
+        current
+    ))]
+    VersionNotNewer {
+        version: NonZeroU64,
+        current: NonZeroU64,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Version number overflow"))]
     VersionOverflow { backtrace: Backtrace },
 
@@ -310,6 +535,12 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to write JSON to stdout: {}", source))]
+    WriteJsonStdout {
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed writing target data to disk: {}", source))]
     WriteTarget {
         source: std::io::Error,
@@ -0,0 +1,226 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A persistent cache of target file hashes, so that unchanged files are not re-hashed every time
+//! a repository is created.
+
+use crate::error::{self, Result};
+use crate::{load_file, write_file};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tough::schema::decoded::{Decoded, Hex};
+
+/// The attributes of a file that, if unchanged, are trusted to mean its contents are unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FileStat {
+    size: u64,
+    inode: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl FileStat {
+    /// Returns `None` if the file's modification time is unavailable, in which case the file
+    /// can't be cached.
+    fn new(metadata: &Metadata) -> Option<Self> {
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+
+        Some(Self {
+            size: metadata.len(),
+            inode,
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+
+    fn mtime(&self) -> Duration {
+        Duration::new(self.mtime_secs, self.mtime_nanos)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    stat: FileStat,
+    sha256: Decoded<Hex>,
+}
+
+#[derive(Debug)]
+pub(crate) struct HashCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// When the cache file was last written. A file modified at or after this time may have been
+    /// modified again, within the filesystem's timestamp granularity, after it was hashed.
+    written: Option<Duration>,
+    /// When this run started. Files modified since then are not cached, for the same reason.
+    started: Duration,
+    /// Entries for the files seen during this run; files that were not seen are dropped when the
+    /// cache is saved.
+    updated: Mutex<BTreeMap<PathBuf, CacheEntry>>,
+}
+
+impl HashCache {
+    /// The coarsest modification time granularity of common filesystems (FAT's two seconds).
+    const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+    /// Loads the cache from `path`, or starts an empty one if the file does not exist.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let (entries, written) = if path.exists() {
+            let written = std::fs::metadata(path)
+                .context(error::FileRead { path })?
+                .modified()
+                .ok()
+                .and_then(|written| written.duration_since(UNIX_EPOCH).ok());
+            (load_file(path)?, written)
+        } else {
+            (HashMap::new(), None)
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+            written,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            updated: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Returns the cached SHA-256 digest for the file at `path`, if its size, inode and
+    /// modification time are unchanged and it was last modified before the cache was written.
+    pub(crate) fn get(&self, path: &Path, metadata: &Metadata) -> Option<Decoded<Hex>> {
+        let stat = FileStat::new(metadata)?;
+        let written = self.written?;
+        self.entries
+            .get(path)
+            .filter(|entry| entry.stat == stat && stat.mtime() < written)
+            .map(|entry| entry.sha256.clone())
+    }
+
+    /// Records the SHA-256 digest of the file at `path`, unless it was modified so recently that
+    /// it might have changed after it was hashed without changing its modification time.
+    pub(crate) fn insert(&self, path: &Path, metadata: &Metadata, sha256: Decoded<Hex>) {
+        let stat = FileStat::new(metadata)
+            .filter(|stat| stat.mtime() + Self::MTIME_GRANULARITY <= self.started);
+        if let Some(stat) = stat {
+            // The map is never left half-updated, so a panic elsewhere doesn't invalidate it.
+            self.updated
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.to_owned(), CacheEntry { stat, sha256 });
+        }
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(error::FileCreate { path: parent })?;
+        }
+        let updated = self.updated.lock().unwrap_or_else(PoisonError::into_inner);
+        write_file(&self.path, &*updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashCache;
+    use std::fs::File;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+    use tough::schema::decoded::{Decoded, Hex};
+
+    fn set_mtime(path: &Path, ago: Duration) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - ago)
+            .unwrap();
+    }
+
+    fn sha256(byte: u8) -> Decoded<Hex> {
+        vec![byte; 32].into()
+    }
+
+    #[test]
+    fn cached() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("cache.json");
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"contents").unwrap();
+        set_mtime(&file, Duration::from_secs(100));
+
+        let cache = HashCache::load(&cache_path).unwrap();
+        cache.insert(&file, &file.metadata().unwrap(), sha256(1));
+        cache.save().unwrap();
+
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.get(&file, &file.metadata().unwrap()), Some(sha256(1)));
+        assert_eq!(
+            cache.get(&dir.path().join("other"), &file.metadata().unwrap()),
+            None
+        );
+
+        // The size changed.
+        std::fs::write(&file, b"other contents").unwrap();
+        set_mtime(&file, Duration::from_secs(100));
+        assert_eq!(cache.get(&file, &file.metadata().unwrap()), None);
+    }
+
+    #[test]
+    fn racy() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("cache.json");
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"contents").unwrap();
+
+        // A file modified just now might change again without its modification time changing.
+        let cache = HashCache::load(&cache_path).unwrap();
+        cache.insert(&file, &file.metadata().unwrap(), sha256(1));
+        cache.save().unwrap();
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.get(&file, &file.metadata().unwrap()), None);
+
+        // A file modified after the cache was written is not trusted either.
+        set_mtime(&file, Duration::from_secs(100));
+        cache.insert(&file, &file.metadata().unwrap(), sha256(1));
+        cache.save().unwrap();
+        set_mtime(&cache_path, Duration::from_secs(200));
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.get(&file, &file.metadata().unwrap()), None);
+    }
+
+    #[test]
+    fn poisoned() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("cache.json");
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"contents").unwrap();
+        set_mtime(&file, Duration::from_secs(100));
+
+        let cache = HashCache::load(&cache_path).unwrap();
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = cache.updated.lock().unwrap();
+                    panic!("poison the lock");
+                })
+                .join()
+                .unwrap_err();
+        });
+        cache.insert(&file, &file.metadata().unwrap(), sha256(1));
+        cache.save().unwrap();
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.get(&file, &file.metadata().unwrap()), Some(sha256(1)));
+    }
+}
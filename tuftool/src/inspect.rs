@@ -0,0 +1,240 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Loads a repository and prints a human-readable (or JSON) summary of its metadata.
+
+use crate::error::{self, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tempfile::tempdir;
+use tough::schema::RoleType;
+use tough::{Limits, Repository, SchemeTransport, Settings, Transport};
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct InspectArgs {
+    /// Path to root.json file for the repository
+    #[structopt(short = "r", long = "root")]
+    root: PathBuf,
+
+    /// TUF repository metadata base URL
+    #[structopt(short = "m", long = "metadata-url")]
+    metadata_base_url: String,
+
+    /// TUF repository target base URL (default: same as the metadata base URL)
+    #[structopt(short = "t", long = "target-url")]
+    target_base_url: Option<String>,
+
+    /// Print JSON instead of tables
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Inspection {
+    consistent_snapshot: bool,
+    roles: BTreeMap<String, RoleInfo>,
+    targets: BTreeMap<String, TargetInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct RoleInfo {
+    version: NonZeroU64,
+    expires: DateTime<Utc>,
+    threshold: Option<NonZeroU64>,
+    keyids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetInfo {
+    length: u64,
+    sha256: String,
+    custom: BTreeMap<String, serde_json::Value>,
+}
+
+impl InspectArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let inspection = self.inspect(&SchemeTransport::new())?;
+
+        if self.json {
+            serde_json::to_writer_pretty(std::io::stdout(), &inspection)
+                .context(error::WriteJsonStdout)?;
+            println!();
+        } else {
+            print_tables(&inspection);
+        }
+        Ok(())
+    }
+
+    fn inspect<T: Transport>(&self, transport: &T) -> Result<Inspection> {
+        let datastore = tempdir().context(error::TempDir)?;
+        let repository = Repository::load(
+            transport,
+            Settings {
+                root: File::open(&self.root).context(error::OpenRoot { path: &self.root })?,
+                datastore: datastore.path(),
+                metadata_base_url: &self.metadata_base_url,
+                target_base_url: self
+                    .target_base_url
+                    .as_ref()
+                    .unwrap_or(&self.metadata_base_url),
+                limits: Limits::default(),
+                observer: None,
+            },
+        )
+        .context(error::Metadata)?;
+
+        let root = &repository.root().signed;
+        let role_info = |role: RoleType, version, expires| {
+            let role_keys = root.roles.get(&role);
+            RoleInfo {
+                version,
+                expires,
+                threshold: role_keys.map(|role_keys| role_keys.threshold),
+                keyids: role_keys
+                    .map(|role_keys| role_keys.keyids.iter().map(hex::encode).collect())
+                    .unwrap_or_default(),
+            }
+        };
+
+        let mut roles = BTreeMap::new();
+        roles.insert(
+            RoleType::Root.to_string(),
+            role_info(RoleType::Root, root.version, root.expires),
+        );
+        roles.insert(
+            RoleType::Snapshot.to_string(),
+            role_info(
+                RoleType::Snapshot,
+                repository.snapshot().signed.version,
+                repository.snapshot().signed.expires,
+            ),
+        );
+        roles.insert(
+            RoleType::Targets.to_string(),
+            role_info(
+                RoleType::Targets,
+                repository.targets_version(),
+                repository.targets_expires(),
+            ),
+        );
+        roles.insert(
+            RoleType::Timestamp.to_string(),
+            role_info(
+                RoleType::Timestamp,
+                repository.timestamp().signed.version,
+                repository.timestamp().signed.expires,
+            ),
+        );
+
+        Ok(Inspection {
+            consistent_snapshot: root.consistent_snapshot,
+            roles,
+            targets: repository
+                .targets()
+                .iter()
+                .map(|(name, target)| {
+                    (
+                        name.clone(),
+                        TargetInfo {
+                            length: target.length,
+                            sha256: hex::encode(&target.sha256),
+                            custom: target
+                                .custom
+                                .iter()
+                                .map(|(k, v)| (k.clone(), v.clone()))
+                                .collect(),
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+}
+
+fn print_tables(inspection: &Inspection) {
+    println!("Consistent snapshot: {}", inspection.consistent_snapshot);
+    println!();
+
+    let mut rows = vec![[
+        "ROLE".to_owned(),
+        "VERSION".to_owned(),
+        "EXPIRES".to_owned(),
+        "THRESHOLD".to_owned(),
+        "KEY IDS".to_owned(),
+    ]];
+    for (name, role) in &inspection.roles {
+        let threshold = role
+            .threshold
+            .map_or_else(|| "-".to_owned(), |t| t.to_string());
+        let mut keyids = role.keyids.iter();
+        rows.push([
+            name.clone(),
+            role.version.to_string(),
+            role.expires.to_rfc3339(),
+            threshold,
+            keyids.next().cloned().unwrap_or_default(),
+        ]);
+        // Additional key IDs are listed on their own lines under the first one.
+        for keyid in keyids {
+            rows.push([
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                keyid.clone(),
+            ]);
+        }
+    }
+    print_rows(&rows);
+    println!();
+
+    let mut rows = vec![[
+        "TARGET".to_owned(),
+        "LENGTH".to_owned(),
+        "SHA256".to_owned(),
+        "CUSTOM".to_owned(),
+    ]];
+    for (name, target) in &inspection.targets {
+        rows.push([
+            name.clone(),
+            target.length.to_string(),
+            target.sha256.clone(),
+            if target.custom.is_empty() {
+                String::new()
+            } else {
+                serde_json::to_string(&target.custom).unwrap_or_default()
+            },
+        ]);
+    }
+    print_rows(&rows);
+}
+
+/// Prints rows of cells, padding each column to the width of its widest cell.
+fn print_rows<R: AsRef<[String]>>(rows: &[R]) {
+    let mut widths = Vec::new();
+    for row in rows {
+        for (i, cell) in row.as_ref().iter().enumerate() {
+            if widths.len() <= i {
+                widths.push(0);
+            }
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    for row in rows {
+        let line = row
+            .as_ref()
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
@@ -5,17 +5,18 @@ use crate::error::{self, Result};
 use crate::source::KeySource;
 use olpc_cjson::CanonicalFormatter;
 use ring::rand::SecureRandom;
-use ring::signature::{KeyPair as _, RsaKeyPair};
+use ring::signature::{EcdsaKeyPair, KeyPair as _, RsaKeyPair};
 use serde::Serialize;
-use snafu::ResultExt;
+use snafu::{OptionExt, ResultExt};
 use std::collections::HashMap;
 use tough::schema::decoded::{Decoded, Hex};
-use tough::schema::key::Key;
+use tough::schema::key::{EcdsaScheme, Key, RsaScheme};
 use tough::schema::{Role, RoleType, Root, Signature, Signed};
 
 #[derive(Debug)]
 pub(crate) enum KeyPair {
     Rsa(RsaKeyPair),
+    Ecdsa(EcdsaKeyPair, EcdsaScheme),
 }
 
 impl KeyPair {
@@ -25,6 +26,16 @@ impl KeyPair {
                 "PRIVATE KEY" => {
                     if let Ok(key_pair) = RsaKeyPair::from_pkcs8(&pem.contents) {
                         Ok(KeyPair::Rsa(key_pair))
+                    } else if let Ok(key_pair) = EcdsaKeyPair::from_pkcs8(
+                        &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+                        &pem.contents,
+                    ) {
+                        Ok(KeyPair::Ecdsa(key_pair, EcdsaScheme::EcdsaSha2Nistp256))
+                    } else if let Ok(key_pair) = EcdsaKeyPair::from_pkcs8(
+                        &ring::signature::ECDSA_P384_SHA384_ASN1_SIGNING,
+                        &pem.contents,
+                    ) {
+                        Ok(KeyPair::Ecdsa(key_pair, EcdsaScheme::EcdsaSha2Nistp384))
                     } else {
                         error::KeyUnrecognized.fail()
                     }
@@ -39,20 +50,35 @@ impl KeyPair {
         }
     }
 
-    pub(crate) fn sign(&self, msg: &[u8], rng: &dyn SecureRandom) -> Result<Vec<u8>> {
-        match self {
-            KeyPair::Rsa(key_pair) => {
+    /// Signs `msg` using the scheme that root.json lists for this key.
+    pub(crate) fn sign(&self, key: &Key, msg: &[u8], rng: &dyn SecureRandom) -> Result<Vec<u8>> {
+        match (self, key) {
+            (KeyPair::Rsa(key_pair), Key::Rsa { scheme, .. }) => {
+                let padding: &dyn ring::signature::RsaEncoding = match scheme {
+                    RsaScheme::RsassaPssSha256 => &ring::signature::RSA_PSS_SHA256,
+                    RsaScheme::RsassaPssSha384 => &ring::signature::RSA_PSS_SHA384,
+                    RsaScheme::RsassaPssSha512 => &ring::signature::RSA_PSS_SHA512,
+                    RsaScheme::RsaPkcs1v15Sha256 => &ring::signature::RSA_PKCS1_SHA256,
+                };
                 let mut signature = vec![0; key_pair.public_modulus_len()];
                 key_pair
-                    .sign(&ring::signature::RSA_PSS_SHA256, rng, msg, &mut signature)
+                    .sign(padding, rng, msg, &mut signature)
                     .context(error::Sign)?;
                 Ok(signature)
             }
+            (KeyPair::Ecdsa(key_pair, ours), Key::Ecdsa { scheme, .. }) if ours == scheme => {
+                Ok(key_pair
+                    .sign(rng, msg)
+                    .context(error::Sign)?
+                    .as_ref()
+                    .to_vec())
+            }
+            _ => error::SchemeMismatch.fail(),
         }
     }
 
     pub(crate) fn public_key(&self) -> Key {
-        use tough::schema::key::{RsaKey, RsaScheme};
+        use tough::schema::key::{EcdsaKey, RsaKey};
 
         match self {
             KeyPair::Rsa(key_pair) => Key::Rsa {
@@ -63,6 +89,14 @@ impl KeyPair {
                 scheme: RsaScheme::RsassaPssSha256,
                 _extra: HashMap::new(),
             },
+            KeyPair::Ecdsa(key_pair, scheme) => Key::Ecdsa {
+                keyval: EcdsaKey {
+                    public: key_pair.public_key().as_ref().to_vec().into(),
+                    _extra: HashMap::new(),
+                },
+                scheme: scheme.clone(),
+                _extra: HashMap::new(),
+            },
         }
     }
 }
@@ -73,6 +107,9 @@ impl PartialEq<Key> for KeyPair {
             (KeyPair::Rsa(key_pair), Key::Rsa { keyval, .. }) => {
                 key_pair.public_key().as_ref() == keyval.public.as_ref()
             }
+            (KeyPair::Ecdsa(key_pair, _), Key::Ecdsa { keyval, .. }) => {
+                key_pair.public_key().as_ref() == keyval.public.as_ref()
+            }
             _ => false,
         }
     }
@@ -107,21 +144,42 @@ pub(crate) fn sign_metadata_inner<T: Serialize>(
     role: &mut Signed<T>,
     rng: &dyn SecureRandom,
 ) -> Result<()> {
+    let data = canonical_payload(&role.signed)?;
+    role.signatures
+        .extend(sign_payload(root, keys, role_type, &data, rng)?);
+    Ok(())
+}
+
+/// Serializes a role as canonical JSON; these are the exact bytes that signatures are made over.
+pub(crate) fn canonical_payload<T: Serialize>(role: &T) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut ser = serde_json::Serializer::with_formatter(&mut data, CanonicalFormatter::new());
+    role.serialize(&mut ser).context(error::SignJson)?;
+    Ok(data)
+}
+
+/// Signs a canonical payload with each key that `root` lists for `role_type`.
+pub(crate) fn sign_payload(
+    root: &Root,
+    keys: &RootKeys,
+    role_type: RoleType,
+    data: &[u8],
+    rng: &dyn SecureRandom,
+) -> Result<Vec<Signature>> {
+    let mut signatures = Vec::new();
     if let Some(role_keys) = root.roles.get(&role_type) {
-        for (keyid, key) in keys {
+        for (keyid, key_pair) in keys {
             if role_keys.keyids.contains(&keyid) {
-                let mut data = Vec::new();
-                let mut ser =
-                    serde_json::Serializer::with_formatter(&mut data, CanonicalFormatter::new());
-                role.signed.serialize(&mut ser).context(error::SignJson)?;
-                let sig = key.sign(&data, rng)?;
-                role.signatures.push(Signature {
+                let key = root.keys.get(keyid).context(error::KeyMissing {
+                    key_id: hex::encode(keyid),
+                })?;
+                let sig = key_pair.sign(key, data, rng)?;
+                signatures.push(Signature {
                     keyid: keyid.clone(),
                     sig: sig.into(),
                 });
             }
         }
     }
-
-    Ok(())
+    Ok(signatures)
 }
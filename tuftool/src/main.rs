@@ -10,13 +10,20 @@ mod copylike;
 mod create;
 mod datetime;
 mod deref;
+mod diff;
 mod download;
 mod error;
+mod hash_cache;
+mod inspect;
 mod key;
+mod prune;
+mod publish;
+mod refresh;
 mod root;
 mod sign;
 mod source;
 mod ssm;
+mod verify;
 
 use crate::error::Result;
 use snafu::{ErrorCompat, OptionExt, ResultExt};
@@ -36,8 +43,21 @@ enum Command {
     Root(root::Command),
     /// Sign a metadata file
     Sign(sign::SignArgs),
+    /// Validate detached signatures and merge them into a metadata file
+    MergeSignatures(sign::MergeSignaturesArgs),
     /// Download a TUF repository's resources
     Download(download::DownloadArgs),
+    /// Print a summary of a TUF repository's metadata
+    Inspect(inspect::InspectArgs),
+    /// Remove metadata and targets that recent snapshots no longer reference
+    Prune(prune::PruneArgs),
+    /// Upload a repository to S3 (or an S3-compatible service), timestamp last
+    #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+    Publish(publish::PublishArgs),
+    /// Re-sign the timestamp or snapshot metadata with a new version and expiration
+    Refresh(refresh::RefreshArgs),
+    /// Verify that a repository on disk is internally consistent
+    Verify(verify::VerifyArgs),
 }
 
 impl Command {
@@ -46,7 +66,14 @@ impl Command {
             Command::Create(args) => args.run(),
             Command::Root(root_subcommand) => root_subcommand.run(),
             Command::Sign(args) => args.run(),
+            Command::MergeSignatures(args) => args.run(),
             Command::Download(args) => args.run(),
+            Command::Inspect(args) => args.run(),
+            Command::Prune(args) => args.run(),
+            #[cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+            Command::Publish(args) => args.run(),
+            Command::Refresh(args) => args.run(),
+            Command::Verify(args) => args.run(),
         }
     }
 }
@@ -0,0 +1,363 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Removes versioned metadata and hash-prefixed targets that recent snapshots no longer reference.
+
+use crate::error::{self, Result};
+use crate::load_file;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use tough::schema::{Root, Signed, Snapshot, Targets, Timestamp};
+use walkdir::WalkDir;
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct PruneArgs {
+    /// Number of most recent snapshots to keep, along with everything they reference
+    #[structopt(long = "keep")]
+    keep: NonZeroUsize,
+
+    /// List the files that would be removed without removing them
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    /// Repository directory (the `outdir` of `tuftool create`)
+    repo: PathBuf,
+}
+
+impl PruneArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let metadir = self.repo.join("metadata");
+        let targetsdir = self.repo.join("targets");
+
+        // Without consistent snapshots, target files are not hash-prefixed and nothing here
+        // describes which of them are still referenced.
+        let root_path = versioned(&metadir, "root.json")?
+            .pop()
+            .map(|(_, path)| path)
+            .context(error::MetadataMissing {
+                path: metadir.join("1.root.json"),
+            })?;
+        let root: Signed<Root> = load_file(&root_path)?;
+        ensure!(
+            root.signed.consistent_snapshot,
+            error::PruneNotConsistent { path: &self.repo }
+        );
+
+        let mut snapshots = versioned(&metadir, "snapshot.json")?;
+        // Every hash-prefixed target not listed by a kept snapshot is removed, so an empty list
+        // here would remove them all.
+        ensure!(
+            !snapshots.is_empty(),
+            error::PruneNoSnapshots { path: &self.repo }
+        );
+        let mut keep_snapshots = snapshots
+            .iter()
+            .rev()
+            .take(self.keep.get())
+            .map(|(version, _)| *version)
+            .collect::<HashSet<_>>();
+        // The snapshot that timestamp.json points to is what clients fetch next, so it stays even
+        // if newer snapshots exist.
+        let timestamp_path = metadir.join("timestamp.json");
+        if timestamp_path.exists() {
+            let timestamp: Signed<Timestamp> = load_file(&timestamp_path)?;
+            if let Some(meta) = timestamp.signed.meta.get("snapshot.json") {
+                keep_snapshots.insert(meta.version.get());
+            }
+        }
+
+        let mut keep_targets = HashSet::new();
+        let mut keep_target_files = HashSet::new();
+        let mut remove = Vec::new();
+        for (version, path) in snapshots.drain(..) {
+            if !keep_snapshots.contains(&version) {
+                remove.push(path);
+                continue;
+            }
+            let snapshot: Signed<Snapshot> = load_file(&path)?;
+            let targets_version = snapshot
+                .signed
+                .meta
+                .get("targets.json")
+                .context(error::MetadataMissing {
+                    path: metadir.join("targets.json"),
+                })?
+                .version
+                .get();
+            if keep_targets.insert(targets_version) {
+                let targets_path = metadir.join(format!("{}.targets.json", targets_version));
+                let targets: Signed<Targets> = load_file(&targets_path)?;
+                for (name, target) in targets.signed.targets {
+                    keep_target_files.insert(format!(
+                        "{}.{}",
+                        hex::encode(&target.hashes.sha256),
+                        name
+                    ));
+                }
+            }
+        }
+
+        for (version, path) in versioned(&metadir, "targets.json")? {
+            if !keep_targets.contains(&version) {
+                remove.push(path);
+            }
+        }
+
+        if targetsdir.exists() {
+            remove.extend(unreferenced_targets(&targetsdir, &keep_target_files)?);
+        }
+
+        remove.sort();
+        for path in &remove {
+            if self.dry_run {
+                println!("Would remove {}", path.display());
+            } else {
+                std::fs::remove_file(path).context(error::FileRemove { path })?;
+                println!("Removed {}", path.display());
+            }
+        }
+        if !self.dry_run && targetsdir.exists() {
+            remove_empty_dirs(&targetsdir)?;
+        }
+        println!(
+            "{} {} file(s)",
+            if self.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            remove.len()
+        );
+        Ok(())
+    }
+}
+
+/// Lists every `N.filename` in `dir`, sorted by version. Root metadata is only listed to find
+/// the latest root, and is never pruned.
+fn versioned(dir: &Path, filename: &str) -> Result<Vec<(u64, PathBuf)>> {
+    let suffix = format!(".{}", filename);
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).context(error::FileRead { path: dir })? {
+        let entry = entry.context(error::FileRead { path: dir })?;
+        let version = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(suffix.as_str()))
+            .and_then(|version| version.parse::<u64>().ok());
+        if let Some(version) = version {
+            files.push((version, entry.path()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Lists the hash-prefixed files in `targetsdir` that are not in `keep`.
+fn unreferenced_targets(targetsdir: &Path, keep: &HashSet<String>) -> Result<Vec<PathBuf>> {
+    let mut unreferenced = Vec::new();
+    for entry in WalkDir::new(targetsdir).min_depth(1) {
+        let entry = entry.context(error::WalkDir)?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .strip_prefix(targetsdir)
+            .context(error::Prefix {
+                path: entry.path(),
+                base: targetsdir,
+            })?;
+        let name = name.to_str().context(error::PathUtf8 { path: name })?;
+        if is_hash_prefixed(name) && !keep.contains(name) {
+            unreferenced.push(entry.path().to_owned());
+        }
+    }
+    Ok(unreferenced)
+}
+
+/// Returns whether a path within the targets directory starts with a SHA-256 digest, as target
+/// files written for consistent snapshots do.
+fn is_hash_prefixed(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 65 && bytes[64] == b'.' && bytes[..64].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Removes directories left empty by pruning nested target names.
+fn remove_empty_dirs(dir: &Path) -> Result<()> {
+    for entry in WalkDir::new(dir).min_depth(1).contents_first(true) {
+        let entry = entry.context(error::WalkDir)?;
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let mut contents = std::fs::read_dir(path).context(error::FileRead { path })?;
+        if contents.next().is_none() {
+            std::fs::remove_dir(path).context(error::FileRemove { path })?;
+            println!("Removed {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PruneArgs;
+    use crate::write_file;
+    use chrono::{DateTime, Utc};
+    use maplit::hashmap;
+    use std::collections::HashMap;
+    use std::num::{NonZeroU64, NonZeroUsize};
+    use std::path::Path;
+    use tempfile::TempDir;
+    use tough::schema::{Hashes, Root, Signed, Snapshot, SnapshotMeta, Target, Targets};
+
+    fn signed<T>(signed: T) -> Signed<T> {
+        Signed {
+            signed,
+            signatures: Vec::new(),
+        }
+    }
+
+    fn version(version: u64) -> NonZeroU64 {
+        NonZeroU64::new(version).unwrap()
+    }
+
+    /// Writes a repository with two snapshots, each referencing its own targets.json with one
+    /// target, plus an unreferenced nested target and a target that is not hash-prefixed.
+    fn write_repo(dir: &Path, consistent_snapshot: bool) {
+        let metadir = dir.join("metadata");
+        let targetsdir = dir.join("targets");
+        std::fs::create_dir_all(&metadir).unwrap();
+        std::fs::create_dir_all(targetsdir.join(format!("{}.dir", "c".repeat(64)))).unwrap();
+
+        let mut root: Signed<Root> = crate::load_file(Path::new(
+            "../tough/tests/data/tuf-reference-impl/metadata/1.root.json",
+        ))
+        .unwrap();
+        root.signed.consistent_snapshot = consistent_snapshot;
+        write_file(&metadir.join("1.root.json"), &root).unwrap();
+
+        let expires = "2030-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        for (n, digest, name) in &[(1, "a", "a.txt"), (2, "b", "b.txt")] {
+            let sha256 = hex::decode(digest.repeat(64)).unwrap();
+            let targets = Targets {
+                spec_version: crate::SPEC_VERSION.to_owned(),
+                version: version(*n),
+                expires,
+                targets: hashmap! {
+                    (*name).to_owned() => Target {
+                        length: 0,
+                        hashes: Hashes { sha256: sha256.into(), _extra: HashMap::new() },
+                        custom: HashMap::new(),
+                        _extra: HashMap::new(),
+                    },
+                },
+                _extra: HashMap::new(),
+            };
+            write_file(
+                &metadir.join(format!("{}.targets.json", n)),
+                &signed(targets),
+            )
+            .unwrap();
+            let snapshot = Snapshot {
+                spec_version: crate::SPEC_VERSION.to_owned(),
+                version: version(*n),
+                expires,
+                meta: hashmap! {
+                    "targets.json".to_owned() => SnapshotMeta {
+                        hashes: None,
+                        length: None,
+                        version: version(*n),
+                        _extra: HashMap::new(),
+                    },
+                },
+                _extra: HashMap::new(),
+            };
+            write_file(
+                &metadir.join(format!("{}.snapshot.json", n)),
+                &signed(snapshot),
+            )
+            .unwrap();
+            std::fs::write(
+                targetsdir.join(format!("{}.{}", digest.repeat(64), name)),
+                b"",
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            targetsdir.join(format!("{}.dir/c.txt", "c".repeat(64))),
+            b"",
+        )
+        .unwrap();
+        std::fs::write(targetsdir.join("plain.txt"), b"").unwrap();
+    }
+
+    fn prune(dir: &Path, dry_run: bool) -> crate::error::Result<()> {
+        PruneArgs {
+            keep: NonZeroUsize::new(1).unwrap(),
+            dry_run,
+            repo: dir.to_owned(),
+        }
+        .run()
+    }
+
+    /// Lists the files under `dir`, relative to it.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files = walkdir::WalkDir::new(dir)
+            .min_depth(1)
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.path().strip_prefix(dir).unwrap();
+                name.to_str().unwrap().replace('\\', "/")
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn keep_latest() {
+        let dir = TempDir::new().unwrap();
+        write_repo(dir.path(), true);
+        let before = files(dir.path());
+
+        prune(dir.path(), true).unwrap();
+        assert_eq!(files(dir.path()), before);
+
+        prune(dir.path(), false).unwrap();
+        assert_eq!(
+            files(dir.path()),
+            vec![
+                "metadata".to_owned(),
+                "metadata/1.root.json".to_owned(),
+                "metadata/2.snapshot.json".to_owned(),
+                "metadata/2.targets.json".to_owned(),
+                "targets".to_owned(),
+                format!("targets/{}.b.txt", "b".repeat(64)),
+                "targets/plain.txt".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuse_unsafe() {
+        let dir = TempDir::new().unwrap();
+        write_repo(dir.path(), false);
+        let before = files(dir.path());
+        assert!(prune(dir.path(), false).is_err());
+        assert_eq!(files(dir.path()), before);
+
+        let dir = TempDir::new().unwrap();
+        write_repo(dir.path(), true);
+        for n in 1..=2 {
+            std::fs::remove_file(dir.path().join(format!("metadata/{}.snapshot.json", n))).unwrap();
+        }
+        let before = files(dir.path());
+        assert!(prune(dir.path(), false).is_err());
+        assert_eq!(files(dir.path()), before);
+    }
+}
@@ -0,0 +1,473 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Uploads a repository built by `tuftool create` to an S3-compatible object store.
+//!
+//! Clients that fetch metadata while an upload is in progress must never see metadata that refers
+//! to files that are not there yet, so each file is uploaded before the metadata that lists it:
+//! targets, then targets metadata (including delegated roles), then snapshot metadata. Clients find
+//! a new snapshot through the timestamp, so the timestamp goes last. Root metadata is fetched by
+//! version rather than through the snapshot, and goes just before the timestamp, so that a new
+//! root and the timestamp signed with it appear as close together as possible.
+
+#![cfg(any(feature = "rusoto-native-tls", feature = "rusoto-rustls"))]
+
+use crate::deref::OptionDeref;
+use crate::error::{self, Result};
+use bytes::Bytes;
+use rusoto_core::{ByteStream, HttpClient, Region, RusotoError};
+use rusoto_credential::DefaultCredentialsProvider;
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, HeadObjectError, HeadObjectRequest,
+    PutObjectRequest, S3Client, UploadPartRequest, S3,
+};
+use sha2::{Digest, Sha256};
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+/// The user metadata key that uploaded objects record their SHA-256 digest in, so that unchanged
+/// objects can be skipped on the next upload.
+const SHA256_METADATA: &str = "sha256";
+
+/// Files larger than this are uploaded in parts of at least this size. A single `PutObject` request
+/// is limited to 5 GiB.
+const PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The most parts S3 accepts for one object.
+const MAX_PARTS: u64 = 10_000;
+
+/// The size of the chunks that request bodies are read from disk in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct PublishArgs {
+    /// Bucket to upload to
+    #[structopt(long = "bucket")]
+    bucket: String,
+
+    /// Prefix for object keys; `metadata/` and `targets/` are uploaded under it
+    #[structopt(long = "prefix", default_value = "")]
+    prefix: String,
+
+    /// Endpoint URL of an S3-compatible service (such as `MinIO`) to use instead of AWS
+    #[structopt(long = "endpoint")]
+    endpoint: Option<String>,
+
+    /// AWS region (default: the profile's region, or the usual AWS environment variables)
+    #[structopt(long = "region")]
+    region: Option<String>,
+
+    /// AWS profile to use for credentials and region
+    #[structopt(long = "profile")]
+    profile: Option<String>,
+
+    /// Repository directory (the `outdir` of `tuftool create`)
+    repo: PathBuf,
+}
+
+/// The order that files are uploaded in; see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Target,
+    TargetsMetadata,
+    Snapshot,
+    Root,
+    Timestamp,
+}
+
+impl Stage {
+    /// Classifies a file in the metadata directory by name. Anything that isn't snapshot, root or
+    /// timestamp metadata is targets metadata, which includes delegated targets roles.
+    fn of_metadata(name: &str) -> Self {
+        if is_role_file(name, "timestamp.json") {
+            Stage::Timestamp
+        } else if is_role_file(name, "root.json") {
+            Stage::Root
+        } else if is_role_file(name, "snapshot.json") {
+            Stage::Snapshot
+        } else {
+            Stage::TargetsMetadata
+        }
+    }
+}
+
+/// Returns whether `name` is `filename` or `N.filename`.
+fn is_role_file(name: &str, filename: &str) -> bool {
+    name == filename
+        || name
+            .strip_suffix(filename)
+            .and_then(|version| version.strip_suffix('.'))
+            .and_then(|version| version.parse::<u64>().ok())
+            .is_some()
+}
+
+impl PublishArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let client = self.build_client()?;
+
+        let mut files = Vec::new();
+        for (dir, metadata) in &[("targets", false), ("metadata", true)] {
+            let base = self.repo.join(dir);
+            if !base.exists() {
+                continue;
+            }
+            for entry in WalkDir::new(&base).follow_links(true).min_depth(1) {
+                let entry = entry.context(error::WalkDir)?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let relative = entry
+                    .path()
+                    .strip_prefix(&self.repo)
+                    .context(error::Prefix {
+                        path: entry.path(),
+                        base: &self.repo,
+                    })?;
+                let name = entry.file_name().to_string_lossy();
+                let stage = if *metadata {
+                    Stage::of_metadata(&name)
+                } else {
+                    Stage::Target
+                };
+                files.push((stage, self.key(relative)?, entry.path().to_owned()));
+            }
+        }
+        files.sort();
+
+        let mut uploaded = 0;
+        for (stage, key, path) in &files {
+            if self.upload(&client, *stage, key, path)? {
+                println!("Uploaded {}", key);
+                uploaded += 1;
+            } else {
+                println!("Skipped {} (unchanged)", key);
+            }
+        }
+        println!(
+            "Uploaded {} object(s), {} unchanged",
+            uploaded,
+            files.len() - uploaded
+        );
+        Ok(())
+    }
+
+    fn build_client(&self) -> Result<S3Client> {
+        let dispatcher = HttpClient::new().context(error::RusotoTls)?;
+        Ok(match self.profile.deref_shim() {
+            Some(profile) => {
+                let (provider, region) = crate::ssm::profile_provider(profile)?;
+                S3Client::new_with(dispatcher, provider, self.region(Some(region))?)
+            }
+            None => S3Client::new_with(
+                dispatcher,
+                DefaultCredentialsProvider::new().context(error::RusotoCreds)?,
+                self.region(None)?,
+            ),
+        })
+    }
+
+    /// Picks the region from `--endpoint` and `--region`, falling back to the profile's region.
+    fn region(&self, profile_region: Option<Region>) -> Result<Region> {
+        Ok(match (&self.endpoint, &self.region) {
+            (Some(endpoint), region) => Region::Custom {
+                name: region
+                    .clone()
+                    .or_else(|| profile_region.map(|region| region.name().to_owned()))
+                    .unwrap_or_else(|| Region::default().name().to_owned()),
+                endpoint: endpoint.clone(),
+            },
+            (None, Some(region)) => Region::from_str(region).context(error::RusotoRegion {
+                region: region.as_str(),
+            })?,
+            (None, None) => profile_region.unwrap_or_default(),
+        })
+    }
+
+    /// Returns the object key for a path relative to the repository directory.
+    fn key(&self, relative: &Path) -> Result<String> {
+        let mut key = self.prefix.trim_end_matches('/').to_owned();
+        for component in relative {
+            let component = component
+                .to_str()
+                .context(error::PathUtf8 { path: relative })?;
+            if !key.is_empty() {
+                key.push('/');
+            }
+            key.push_str(component);
+        }
+        Ok(key)
+    }
+
+    /// Uploads a file unless the object already exists with the same SHA-256 digest. Returns
+    /// whether the file was uploaded.
+    // S3 object sizes are far below `i64::MAX`.
+    #[allow(clippy::cast_possible_wrap)]
+    fn upload(&self, client: &S3Client, stage: Stage, key: &str, path: &Path) -> Result<bool> {
+        let mut file = File::open(path).context(error::FileOpen { path })?;
+        let mut digest = Sha256::new();
+        let length = std::io::copy(&mut file, &mut digest).context(error::FileRead { path })?;
+        let sha256 = hex::encode(digest.result());
+
+        let existing = match client
+            .head_object(HeadObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.to_owned(),
+                ..HeadObjectRequest::default()
+            })
+            .sync()
+        {
+            Ok(output) => output.metadata,
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => None,
+            // HEAD responses have no body, so a missing object usually shows up as a bare 404.
+            Err(RusotoError::Unknown(ref response)) if response.status.as_u16() == 404 => None,
+            Err(err) => {
+                return Err(err).context(error::S3HeadObject {
+                    bucket: &self.bucket,
+                    key,
+                })
+            }
+        };
+        if unchanged(existing.as_ref(), &sha256) {
+            return Ok(false);
+        }
+
+        let content_type = if stage == Stage::Target {
+            "application/octet-stream"
+        } else {
+            "application/json"
+        };
+        let mut metadata = HashMap::new();
+        metadata.insert(SHA256_METADATA.to_owned(), sha256);
+        if length <= PART_SIZE {
+            client
+                .put_object(PutObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    content_type: Some(content_type.to_owned()),
+                    content_length: Some(length as i64),
+                    metadata: Some(metadata),
+                    body: Some(body(file, 0, length).context(error::FileRead { path })?),
+                    ..PutObjectRequest::default()
+                })
+                .sync()
+                .context(error::S3PutObject {
+                    bucket: &self.bucket,
+                    key,
+                })?;
+        } else {
+            let upload_id = client
+                .create_multipart_upload(CreateMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    content_type: Some(content_type.to_owned()),
+                    metadata: Some(metadata),
+                    ..CreateMultipartUploadRequest::default()
+                })
+                .sync()
+                .context(error::S3CreateMultipartUpload {
+                    bucket: &self.bucket,
+                    key,
+                })?
+                .upload_id
+                .context(error::S3UploadIdMissing {
+                    bucket: &self.bucket,
+                    key,
+                })?;
+            let result = self.upload_parts(client, key, path, &file, length, &upload_id);
+            if result.is_err() {
+                // Parts of an abandoned upload are billed until it is aborted.
+                if let Err(err) = client
+                    .abort_multipart_upload(AbortMultipartUploadRequest {
+                        bucket: self.bucket.clone(),
+                        key: key.to_owned(),
+                        upload_id,
+                        ..AbortMultipartUploadRequest::default()
+                    })
+                    .sync()
+                {
+                    eprintln!("Failed to abort upload of {}: {}", key, err);
+                }
+            }
+            result?;
+        }
+        Ok(true)
+    }
+
+    /// Uploads the parts of a multipart upload and completes it.
+    #[allow(clippy::cast_possible_wrap)]
+    fn upload_parts(
+        &self,
+        client: &S3Client,
+        key: &str,
+        path: &Path,
+        file: &File,
+        length: u64,
+        upload_id: &str,
+    ) -> Result<()> {
+        let mut completed = Vec::new();
+        for (index, (offset, size)) in parts(length).into_iter().enumerate() {
+            let part_number = index as i64 + 1;
+            let file = file.try_clone().context(error::FileRead { path })?;
+            let output = client
+                .upload_part(UploadPartRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_owned(),
+                    upload_id: upload_id.to_owned(),
+                    part_number,
+                    content_length: Some(size as i64),
+                    body: Some(body(file, offset, size).context(error::FileRead { path })?),
+                    ..UploadPartRequest::default()
+                })
+                .sync()
+                .context(error::S3UploadPart {
+                    bucket: &self.bucket,
+                    key,
+                    part_number,
+                })?;
+            completed.push(CompletedPart {
+                e_tag: output.e_tag,
+                part_number: Some(part_number),
+            });
+        }
+        client
+            .complete_multipart_upload(CompleteMultipartUploadRequest {
+                bucket: self.bucket.clone(),
+                key: key.to_owned(),
+                upload_id: upload_id.to_owned(),
+                multipart_upload: Some(CompletedMultipartUpload {
+                    parts: Some(completed),
+                }),
+                ..CompleteMultipartUploadRequest::default()
+            })
+            .sync()
+            .context(error::S3CompleteMultipartUpload {
+                bucket: &self.bucket,
+                key,
+            })?;
+        Ok(())
+    }
+}
+
+/// Returns whether an existing object's user metadata records the digest `sha256`.
+fn unchanged(existing: Option<&HashMap<String, String>>, sha256: &str) -> bool {
+    existing
+        .and_then(|metadata| metadata.get(SHA256_METADATA))
+        .map(String::as_str)
+        == Some(sha256)
+}
+
+/// Splits `length` bytes into `(offset, size)` parts of `PART_SIZE` bytes, or larger parts if
+/// that would take more than `MAX_PARTS`.
+fn parts(length: u64) -> Vec<(u64, u64)> {
+    let part_size = std::cmp::max(PART_SIZE, length.div_ceil(MAX_PARTS));
+    let mut parts = Vec::new();
+    let mut offset = 0;
+    while offset < length {
+        let size = std::cmp::min(part_size, length - offset);
+        parts.push((offset, size));
+        offset += size;
+    }
+    parts
+}
+
+/// Streams `size` bytes of `file`, starting at `offset`, as a request body.
+fn body(mut file: File, offset: u64, size: u64) -> std::io::Result<ByteStream> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = file.take(size);
+    let chunks = std::iter::from_fn(move || {
+        let mut buf = vec![0; CHUNK_SIZE];
+        match reader.read(&mut buf) {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some(Ok(Bytes::from(buf)))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    });
+    Ok(ByteStream::new(futures::stream::iter_result(chunks)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parts, unchanged, PublishArgs, Stage, MAX_PARTS, PART_SIZE, SHA256_METADATA};
+    use maplit::hashmap;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn upload_order() {
+        let mut files = [
+            (Stage::of_metadata("timestamp.json"), "timestamp.json"),
+            (Stage::of_metadata("2.root.json"), "2.root.json"),
+            (Stage::of_metadata("3.snapshot.json"), "3.snapshot.json"),
+            (Stage::of_metadata("3.targets.json"), "3.targets.json"),
+            (Stage::of_metadata("foo.json"), "foo.json"),
+            (Stage::Target, "abc.file"),
+        ];
+        files.sort();
+        assert_eq!(
+            files.iter().map(|(_, name)| *name).collect::<Vec<_>>(),
+            vec![
+                "abc.file",
+                "3.targets.json",
+                "foo.json",
+                "3.snapshot.json",
+                "2.root.json",
+                "timestamp.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let key = |prefix: &str, relative: &str| {
+            PublishArgs {
+                bucket: "bucket".to_owned(),
+                prefix: prefix.to_owned(),
+                endpoint: None,
+                region: None,
+                profile: None,
+                repo: PathBuf::from("repo"),
+            }
+            .key(Path::new(relative))
+            .unwrap()
+        };
+        assert_eq!(key("", "metadata/1.root.json"), "metadata/1.root.json");
+        assert_eq!(
+            key("tuf", "metadata/1.root.json"),
+            "tuf/metadata/1.root.json"
+        );
+        assert_eq!(key("tuf/", "targets/a/b.txt"), "tuf/targets/a/b.txt");
+        assert_eq!(key("a/b//", "targets/c.txt"), "a/b/targets/c.txt");
+    }
+
+    #[test]
+    fn skip_by_digest() {
+        let metadata = hashmap! { SHA256_METADATA.to_owned() => "abcd".to_owned() };
+        assert!(unchanged(Some(&metadata), "abcd"));
+        assert!(!unchanged(Some(&metadata), "abce"));
+        assert!(!unchanged(Some(&hashmap! {}), "abcd"));
+        assert!(!unchanged(None, "abcd"));
+    }
+
+    #[test]
+    fn part_sizes() {
+        assert_eq!(parts(0), vec![]);
+        assert_eq!(parts(PART_SIZE + 1), vec![(0, PART_SIZE), (PART_SIZE, 1)]);
+
+        let length = PART_SIZE * MAX_PARTS * 3 + 5;
+        let parts = parts(length);
+        assert_eq!(parts.len() as u64, MAX_PARTS);
+        assert_eq!(parts.iter().map(|(_, size)| size).sum::<u64>(), length);
+        assert!(parts
+            .windows(2)
+            .all(|pair| pair[0].0 + pair[0].1 == pair[1].0));
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Re-signs the timestamp or snapshot role of an existing repository with a new version and
+//! expiration, without needing access to any other role's keys.
+
+use crate::datetime::parse_datetime;
+use crate::error::{self, Result};
+use crate::key::{keys_for_root, sign_metadata};
+use crate::source::KeySource;
+use crate::{load_file, write_file};
+use chrono::{DateTime, Utc};
+use ring::rand::SystemRandom;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use tough::schema::{
+    Hashes, Role, RoleType, Root, Signed, Snapshot, SnapshotMeta, Targets, Timestamp, TimestampMeta,
+};
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct RefreshArgs {
+    /// Key files to sign with
+    #[structopt(short = "k", long = "key")]
+    keys: Vec<KeySource>,
+
+    /// Path to root.json file for the repository
+    #[structopt(short = "r", long = "root")]
+    root: PathBuf,
+
+    /// The role to refresh (timestamp or snapshot)
+    #[structopt(long = "role")]
+    role: RoleType,
+
+    /// Version of the refreshed metadata file; must be newer than the current version (default:
+    /// current version + 1)
+    #[structopt(long = "version")]
+    version: Option<NonZeroU64>,
+    /// Expiration of the refreshed metadata file; can be in full RFC 3339 format, or something
+    /// like 'in 7 days'
+    #[structopt(long = "expires", parse(try_from_str = parse_datetime))]
+    expires: DateTime<Utc>,
+
+    /// Repository directory (the `outdir` of `tuftool create`)
+    repo: PathBuf,
+}
+
+impl RefreshArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let root_buf = std::fs::read(&self.root).context(error::FileRead { path: &self.root })?;
+        let root = serde_json::from_slice::<Signed<Root>>(&root_buf)
+            .context(error::FileParseJson { path: &self.root })?
+            .signed;
+
+        let process = RefreshProcess {
+            args: self,
+            keys: keys_for_root(&self.keys, &root)?,
+            metadir: self.repo.join("metadata"),
+            root,
+            root_buf,
+        };
+        match self.role {
+            RoleType::Timestamp => process.refresh_timestamp(),
+            RoleType::Snapshot => process.refresh_snapshot(),
+            role => error::RefreshRole { role }.fail(),
+        }
+    }
+}
+
+struct RefreshProcess<'a> {
+    args: &'a RefreshArgs,
+    keys: crate::key::RootKeys,
+    metadir: PathBuf,
+    root: Root,
+    root_buf: Vec<u8>,
+}
+
+impl<'a> RefreshProcess<'a> {
+    fn refresh_timestamp(&self) -> Result<()> {
+        let path = self.metadir.join("timestamp.json");
+        let mut timestamp: Signed<Timestamp> = load_file(&path)?;
+
+        let snapshot_path = self.latest("snapshot.json")?;
+        let (snapshot, buf) = load_with_bytes::<Snapshot>(&snapshot_path)?;
+        timestamp.signed.meta.insert(
+            "snapshot.json".to_owned(),
+            TimestampMeta {
+                length: buf.len() as u64,
+                hashes: hashes(&buf),
+                version: snapshot.signed.version,
+                _extra: HashMap::new(),
+            },
+        );
+
+        timestamp.signed.version = self.next_version(timestamp.signed.version)?;
+        timestamp.signed.expires = self.args.expires;
+        self.sign_and_write(timestamp, &path)
+    }
+
+    fn refresh_snapshot(&self) -> Result<()> {
+        let mut snapshot: Signed<Snapshot> = load_file(&self.latest("snapshot.json")?)?;
+
+        let targets_path = self.latest("targets.json")?;
+        let (targets, buf) = load_with_bytes::<Targets>(&targets_path)?;
+        set_snapshot_meta(
+            &mut snapshot.signed,
+            "targets.json",
+            &buf,
+            targets.signed.version,
+        );
+        set_snapshot_meta(
+            &mut snapshot.signed,
+            "root.json",
+            &self.root_buf,
+            self.root.version,
+        );
+
+        snapshot.signed.version = self.next_version(snapshot.signed.version)?;
+        snapshot.signed.expires = self.args.expires;
+        let path = if self.root.consistent_snapshot {
+            self.metadir
+                .join(format!("{}.snapshot.json", snapshot.signed.version))
+        } else {
+            self.metadir.join("snapshot.json")
+        };
+        self.sign_and_write(snapshot, &path)
+    }
+
+    // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+    /// Returns the path to the most recent version of a metadata file. With consistent snapshots
+    /// this is the `N.filename` with the highest `N`.
+    fn latest(&self, filename: &str) -> Result<PathBuf> {
+        if !self.root.consistent_snapshot {
+            return Ok(self.metadir.join(filename));
+        }
+
+        let suffix = format!(".{}", filename);
+        let mut latest = None;
+        for entry in std::fs::read_dir(&self.metadir).context(error::FileRead {
+            path: &self.metadir,
+        })? {
+            let entry = entry.context(error::FileRead {
+                path: &self.metadir,
+            })?;
+            let name = entry.file_name();
+            let version = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(suffix.as_str()))
+                .and_then(|version| version.parse::<u64>().ok());
+            match (version, &latest) {
+                (Some(version), Some((latest_version, _))) if version <= *latest_version => {}
+                (Some(version), _) => latest = Some((version, entry.path())),
+                (None, _) => {}
+            }
+        }
+        latest
+            .map(|(_, path)| path)
+            .context(error::MetadataMissing {
+                path: self.metadir.join(format!("N.{}", filename)),
+            })
+    }
+
+    fn next_version(&self, current: NonZeroU64) -> Result<NonZeroU64> {
+        next_version(self.args.version, current)
+    }
+
+    fn sign_and_write<T: Role + Serialize>(&self, mut role: Signed<T>, path: &Path) -> Result<()> {
+        role.signatures.clear();
+        sign_metadata(&self.root, &self.keys, &mut role, &SystemRandom::new())?;
+        // Refuse to write a role that clients would reject.
+        self.root
+            .verify_role(&role)
+            .context(error::VerifySigned { role: T::TYPE })?;
+        write_file(path, &role)
+    }
+}
+
+/// Loads a metadata file, returning both the parsed role and the exact bytes that were read (for
+/// hashing).
+fn load_with_bytes<T>(path: &Path) -> Result<(Signed<T>, Vec<u8>)>
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    let buf = std::fs::read(path).context(error::FileRead { path })?;
+    let role = serde_json::from_slice(&buf).context(error::FileParseJson { path })?;
+    Ok((role, buf))
+}
+
+/// Returns the version to write: `requested` if given, otherwise `current + 1`. A requested version
+/// that isn't newer than `current` is an error, because clients would reject the metadata as a
+/// rollback.
+fn next_version(requested: Option<NonZeroU64>, current: NonZeroU64) -> Result<NonZeroU64> {
+    if let Some(version) = requested {
+        ensure!(
+            version > current,
+            error::VersionNotNewer { version, current }
+        );
+        return Ok(version);
+    }
+    NonZeroU64::new(
+        current
+            .get()
+            .checked_add(1)
+            .context(error::VersionOverflow)?,
+    )
+    .context(error::VersionZero)
+}
+
+fn hashes(buf: &[u8]) -> Hashes {
+    Hashes {
+        sha256: Sha256::digest(buf).as_slice().to_vec().into(),
+        _extra: HashMap::new(),
+    }
+}
+
+fn set_snapshot_meta(snapshot: &mut Snapshot, file: &str, buf: &[u8], version: NonZeroU64) {
+    snapshot.meta.insert(
+        file.to_owned(),
+        SnapshotMeta {
+            length: Some(buf.len() as u64),
+            hashes: Some(hashes(buf)),
+            version,
+            _extra: HashMap::new(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_version;
+    use crate::error::Error;
+    use std::num::NonZeroU64;
+
+    fn version(n: u64) -> NonZeroU64 {
+        NonZeroU64::new(n).unwrap()
+    }
+
+    #[test]
+    fn next_version_increases() {
+        assert_eq!(next_version(None, version(5)).unwrap(), version(6));
+        assert_eq!(
+            next_version(Some(version(9)), version(5)).unwrap(),
+            version(9)
+        );
+        assert!(matches!(
+            next_version(Some(version(1)), version(5)),
+            Err(Error::VersionNotNewer { .. })
+        ));
+        assert!(matches!(
+            next_version(Some(version(5)), version(5)),
+            Err(Error::VersionNotNewer { .. })
+        ));
+        assert!(matches!(
+            next_version(None, version(u64::MAX)),
+            Err(Error::VersionOverflow { .. })
+        ));
+    }
+}
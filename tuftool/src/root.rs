@@ -2,19 +2,23 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::datetime::parse_datetime;
+use crate::diff::{diff_roots, key_field, Change};
 use crate::error::{self, Result};
 use crate::key::KeyPair;
 use crate::source::KeySource;
 use crate::{load_file, write_file};
 use chrono::{DateTime, Timelike, Utc};
 use maplit::hashmap;
+use sha2::{Digest, Sha256};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::collections::HashMap;
 use std::num::NonZeroU64;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use structopt::StructOpt;
 use tough::schema::decoded::{Decoded, Hex};
-use tough::schema::{key::Key, RoleKeys, RoleType, Root, Signed};
+use tough::schema::key::{Key, RsaScheme};
+use tough::schema::{RoleKeys, RoleType, Root, Signed};
 
 #[derive(Debug, StructOpt)]
 pub(crate) enum Command {
@@ -27,6 +31,9 @@ pub(crate) enum Command {
     BumpVersion {
         /// Path to root.json
         path: PathBuf,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
     },
     /// Set the expiration time for root.json
     Expire {
@@ -36,6 +43,9 @@ pub(crate) enum Command {
         /// 7 days'
         #[structopt(parse(try_from_str = parse_datetime))]
         time: DateTime<Utc>,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
     },
     /// Set the signature count threshold for a role
     SetThreshold {
@@ -45,6 +55,9 @@ pub(crate) enum Command {
         role: RoleType,
         /// The new threshold
         threshold: NonZeroU64,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
     },
     /// Add a key (public or private) to a role
     AddKey {
@@ -55,6 +68,12 @@ pub(crate) enum Command {
         /// The role to add the key to
         #[structopt(short = "r", long = "role")]
         roles: Vec<RoleType>,
+        /// Signature scheme for an RSA key (default: rsassa-pss-sha256)
+        #[structopt(long = "rsa-scheme", parse(try_from_str = parse_rsa_scheme))]
+        rsa_scheme: Option<RsaScheme>,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
     },
     /// Remove a key ID, either entirely or from a single role
     RemoveKey {
@@ -65,6 +84,28 @@ pub(crate) enum Command {
         /// Role to remove the key ID from (if provided, the public key will still be listed in the
         /// file)
         role: Option<RoleType>,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Print a public key from root.json in a standard format
+    ExportKey {
+        /// Path to root.json
+        path: PathBuf,
+        /// The key ID to export
+        key_id: Decoded<Hex>,
+        /// Output format
+        #[structopt(
+            long = "format",
+            default_value = "pem",
+            possible_values = &["pem", "openssh", "jwk", "tuf-json"]
+        )]
+        format: KeyFormat,
+    },
+    /// List the keys in root.json with the roles that use them
+    ListKeys {
+        /// Path to root.json
+        path: PathBuf,
     },
     /// Generate a new RSA key pair, saving it to a file, and add it to a role
     GenRsaKey {
@@ -81,9 +122,56 @@ pub(crate) enum Command {
         /// The role to add the key to
         #[structopt(short = "r", long = "role")]
         roles: Vec<RoleType>,
+        /// Signature scheme for the new key (default: rsassa-pss-sha256)
+        #[structopt(long = "rsa-scheme", parse(try_from_str = parse_rsa_scheme))]
+        rsa_scheme: Option<RsaScheme>,
+        /// Print the changes that would be made instead of writing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Check that clients trusting the old root.json will accept the new one
+    CheckRotation {
+        /// Path to the currently trusted root.json
+        old: PathBuf,
+        /// Path to the new root.json
+        new: PathBuf,
+    },
+    /// Show the changes between two root.json files
+    Diff {
+        /// Path to the old root.json
+        old: PathBuf,
+        /// Path to the new root.json
+        new: PathBuf,
     },
 }
 
+/// Formats that `root export-key` can write a public key in.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum KeyFormat {
+    /// PEM-encoded `SubjectPublicKeyInfo`
+    Pem,
+    /// An OpenSSH public key line
+    OpenSsh,
+    /// A JSON Web Key
+    Jwk,
+    /// The key object as it appears in root.json
+    TufJson,
+}
+
+impl FromStr for KeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pem" => Ok(KeyFormat::Pem),
+            "openssh" => Ok(KeyFormat::OpenSsh),
+            "jwk" => Ok(KeyFormat::Jwk),
+            "tuf-json" => Ok(KeyFormat::TufJson),
+            _ => Err(format!("unknown key format {}", s)),
+        }
+    }
+}
+
 macro_rules! role_keys {
     ($threshold:expr) => {
         RoleKeys {
@@ -102,134 +190,362 @@ macro_rules! role_keys {
 impl Command {
     pub(crate) fn run(&self) -> Result<()> {
         match self {
-            Command::Init { path } => write_file(
+            Command::Init { path } => init(path),
+            Command::BumpVersion { path, dry_run } => bump_version(path, *dry_run),
+            Command::Expire {
                 path,
-                &Signed {
-                    signed: Root {
-                        spec_version: crate::SPEC_VERSION.to_owned(),
-                        consistent_snapshot: true,
-                        version: NonZeroU64::new(1).unwrap(),
-                        expires: round_time(Utc::now()),
-                        keys: HashMap::new(),
-                        roles: hashmap! {
-                            RoleType::Root => role_keys!(),
-                            RoleType::Snapshot => role_keys!(),
-                            RoleType::Targets => role_keys!(),
-                            RoleType::Timestamp => role_keys!(),
-                        },
-                        _extra: HashMap::new(),
-                    },
-                    signatures: Vec::new(),
-                },
-            ),
-            Command::BumpVersion { path } => {
-                let mut root: Signed<Root> = load_file(path)?;
-                root.signed.version = NonZeroU64::new(
-                    root.signed
-                        .version
-                        .get()
-                        .checked_add(1)
-                        .context(error::VersionOverflow)?,
-                )
-                .context(error::VersionZero)?;
-                clear_sigs(&mut root);
-                write_file(path, &root)
-            }
-            Command::Expire { path, time } => {
-                let mut root: Signed<Root> = load_file(path)?;
-                root.signed.expires = round_time(*time);
-                clear_sigs(&mut root);
-                write_file(path, &root)
-            }
+                time,
+                dry_run,
+            } => expire(path, *time, *dry_run),
             Command::SetThreshold {
                 path,
                 role,
                 threshold,
-            } => {
-                let mut root: Signed<Root> = load_file(path)?;
-                root.signed
-                    .roles
-                    .entry(*role)
-                    .and_modify(|rk| rk.threshold = *threshold)
-                    .or_insert_with(|| role_keys!(*threshold));
-                clear_sigs(&mut root);
-                write_file(path, &root)
-            }
+                dry_run,
+            } => set_threshold(path, *role, *threshold, *dry_run),
             Command::AddKey {
                 path,
                 roles,
                 key_path,
-            } => {
-                let mut root: Signed<Root> = load_file(path)?;
-                let key_pair = key_path.as_public_key()?;
-                let key_id = hex::encode(add_key(&mut root.signed, roles, key_pair)?);
-                clear_sigs(&mut root);
-                println!("{}", key_id);
-                write_file(path, &root)
-            }
-            Command::RemoveKey { path, key_id, role } => {
-                let mut root: Signed<Root> = load_file(path)?;
-                if let Some(role) = role {
-                    if let Some(role_keys) = root.signed.roles.get_mut(role) {
-                        role_keys
-                            .keyids
-                            .iter()
-                            .position(|k| k.eq(key_id))
-                            .map(|pos| role_keys.keyids.remove(pos));
-                    }
-                } else {
-                    for role_keys in root.signed.roles.values_mut() {
-                        role_keys
-                            .keyids
-                            .iter()
-                            .position(|k| k.eq(key_id))
-                            .map(|pos| role_keys.keyids.remove(pos));
-                    }
-                    root.signed.keys.remove(key_id);
-                }
-                clear_sigs(&mut root);
-                write_file(path, &root)
-            }
+                rsa_scheme,
+                dry_run,
+            } => add_key(path, roles, key_path, rsa_scheme.as_ref(), *dry_run),
+            Command::RemoveKey {
+                path,
+                key_id,
+                role,
+                dry_run,
+            } => remove_key(path, key_id, *role, *dry_run),
+            Command::ExportKey {
+                path,
+                key_id,
+                format,
+            } => export_key(path, key_id, *format),
+            Command::ListKeys { path } => list_keys(path),
             Command::GenRsaKey {
                 path,
                 roles,
                 key_path,
                 bits,
                 exponent,
-            } => {
-                let mut root: Signed<Root> = load_file(path)?;
-
-                // ring doesn't support RSA key generation yet
-                // https://github.com/briansmith/ring/issues/219
-                let mut command = std::process::Command::new("openssl");
-                command.args(&["genpkey", "-algorithm", "RSA", "-pkeyopt"]);
-                command.arg(format!("rsa_keygen_bits:{}", bits));
-                command.arg("-pkeyopt");
-                command.arg(format!("rsa_keygen_pubexp:{}", exponent));
-
-                let command_str = format!("{:?}", command);
-                let output = command.output().context(error::CommandExec {
-                    command_str: &command_str,
-                })?;
-                ensure!(
-                    output.status.success(),
-                    error::CommandStatus {
-                        command_str: &command_str,
-                        status: output.status
-                    }
-                );
-                let stdout =
-                    String::from_utf8(output.stdout).context(error::CommandUtf8 { command_str })?;
-
-                let key_pair = KeyPair::parse(stdout.as_bytes())?;
-                let key_id = hex::encode(add_key(&mut root.signed, roles, key_pair.public_key())?);
-                key_path.write(&stdout, &key_id)?;
-                clear_sigs(&mut root);
-                println!("{}", key_id);
-                write_file(path, &root)
+                rsa_scheme,
+                dry_run,
+            } => gen_rsa_key(
+                path,
+                roles,
+                key_path,
+                (*bits, *exponent),
+                rsa_scheme.as_ref(),
+                *dry_run,
+            ),
+            Command::CheckRotation { old, new } => {
+                check_rotation(&load_file(old)?, &load_file(new)?)
+            }
+            Command::Diff { old, new } => {
+                let old: Signed<Root> = load_file(old)?;
+                let new: Signed<Root> = load_file(new)?;
+                print_diff(&old.signed, &new.signed);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn init(path: &Path) -> Result<()> {
+    write_file(
+        path,
+        &Signed {
+            signed: Root {
+                spec_version: crate::SPEC_VERSION.to_owned(),
+                consistent_snapshot: true,
+                version: NonZeroU64::new(1).unwrap(),
+                expires: round_time(Utc::now()),
+                keys: HashMap::new(),
+                roles: hashmap! {
+                    RoleType::Root => role_keys!(),
+                    RoleType::Snapshot => role_keys!(),
+                    RoleType::Targets => role_keys!(),
+                    RoleType::Timestamp => role_keys!(),
+                },
+                _extra: HashMap::new(),
+            },
+            signatures: Vec::new(),
+        },
+    )
+}
+
+fn bump_version(path: &Path, dry_run: bool) -> Result<()> {
+    update(path, dry_run, |root| {
+        root.version = NonZeroU64::new(
+            root.version
+                .get()
+                .checked_add(1)
+                .context(error::VersionOverflow)?,
+        )
+        .context(error::VersionZero)?;
+        Ok(())
+    })
+}
+
+fn expire(path: &Path, time: DateTime<Utc>, dry_run: bool) -> Result<()> {
+    update(path, dry_run, |root| {
+        root.expires = round_time(time);
+        Ok(())
+    })
+}
+
+fn set_threshold(path: &Path, role: RoleType, threshold: NonZeroU64, dry_run: bool) -> Result<()> {
+    update(path, dry_run, |root| {
+        root.roles
+            .entry(role)
+            .and_modify(|rk| rk.threshold = threshold)
+            .or_insert_with(|| role_keys!(threshold));
+        Ok(())
+    })
+}
+
+fn add_key(
+    path: &Path,
+    roles: &[RoleType],
+    key_path: &KeySource,
+    rsa_scheme: Option<&RsaScheme>,
+    dry_run: bool,
+) -> Result<()> {
+    update(path, dry_run, |root| {
+        let mut key = key_path.as_public_key()?;
+        set_rsa_scheme(&mut key, rsa_scheme)?;
+        let key_id = hex::encode(add_key_to_root(root, roles, key)?);
+        println!("{}", key_id);
+        Ok(())
+    })
+}
+
+fn remove_key(
+    path: &Path,
+    key_id: &Decoded<Hex>,
+    role: Option<RoleType>,
+    dry_run: bool,
+) -> Result<()> {
+    update(path, dry_run, |root| {
+        if let Some(role) = role {
+            if let Some(role_keys) = root.roles.get_mut(&role) {
+                role_keys
+                    .keyids
+                    .iter()
+                    .position(|k| k.eq(key_id))
+                    .map(|pos| role_keys.keyids.remove(pos));
             }
+        } else {
+            for role_keys in root.roles.values_mut() {
+                role_keys
+                    .keyids
+                    .iter()
+                    .position(|k| k.eq(key_id))
+                    .map(|pos| role_keys.keyids.remove(pos));
+            }
+            root.keys.remove(key_id);
+        }
+        Ok(())
+    })
+}
+
+fn export_key(path: &Path, key_id: &Decoded<Hex>, format: KeyFormat) -> Result<()> {
+    let root: Signed<Root> = load_file(path)?;
+    let key = root.signed.keys.get(key_id).context(error::KeyMissing {
+        key_id: hex::encode(key_id),
+    })?;
+    println!("{}", format_key(key, format, key_id)?);
+    Ok(())
+}
+
+/// Generates an RSA key pair with the given `(bits, exponent)`, writes it to `key_path`, and adds
+/// its public key to `roles`.
+fn gen_rsa_key(
+    path: &Path,
+    roles: &[RoleType],
+    key_path: &KeySource,
+    (bits, exponent): (u16, u32),
+    rsa_scheme: Option<&RsaScheme>,
+    dry_run: bool,
+) -> Result<()> {
+    update(path, dry_run, |root| {
+        // ring doesn't support RSA key generation yet
+        // https://github.com/briansmith/ring/issues/219
+        let mut command = std::process::Command::new("openssl");
+        command.args(&["genpkey", "-algorithm", "RSA", "-pkeyopt"]);
+        command.arg(format!("rsa_keygen_bits:{}", bits));
+        command.arg("-pkeyopt");
+        command.arg(format!("rsa_keygen_pubexp:{}", exponent));
+
+        let command_str = format!("{:?}", command);
+        let output = command.output().context(error::CommandExec {
+            command_str: &command_str,
+        })?;
+        ensure!(
+            output.status.success(),
+            error::CommandStatus {
+                command_str: &command_str,
+                status: output.status
+            }
+        );
+        let stdout =
+            String::from_utf8(output.stdout).context(error::CommandUtf8 { command_str })?;
+
+        let mut key = KeyPair::parse(stdout.as_bytes())?.public_key();
+        set_rsa_scheme(&mut key, rsa_scheme)?;
+        let key_id = hex::encode(add_key_to_root(root, roles, key)?);
+        if !dry_run {
+            key_path.write(&stdout, &key_id)?;
+        }
+        println!("{}", key_id);
+        Ok(())
+    })
+}
+
+/// Loads root.json, applies `f` to it, and writes it back out with its signatures cleared. If
+/// `dry_run` is set, the changes (including the signatures that would be removed) are printed and
+/// nothing is written.
+fn update<F>(path: &Path, dry_run: bool, f: F) -> Result<()>
+where
+    F: FnOnce(&mut Root) -> Result<()>,
+{
+    let mut root: Signed<Root> = load_file(path)?;
+    let old = root.signed.clone();
+    f(&mut root.signed)?;
+    if dry_run {
+        let mut changes = diff_roots(&old, &root.signed);
+        changes.extend(root.signatures.iter().map(|signature| {
+            Change::Removed(format!("signature by {}", hex::encode(&signature.keyid)))
+        }));
+        print_changes(changes);
+        Ok(())
+    } else {
+        clear_sigs(&mut root);
+        write_file(path, &root)
+    }
+}
+
+fn print_diff(old: &Root, new: &Root) {
+    print_changes(diff_roots(old, new));
+}
+
+fn print_changes(changes: Vec<Change>) {
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    for change in changes {
+        println!("{}", change);
+    }
+}
+
+/// Performs the same checks clients perform when updating from root version N to N+1: the new root
+/// must be signed by a threshold of the old root's root keys and a threshold of its own root keys,
+/// and its version must be exactly N+1.
+fn check_rotation(old: &Signed<Root>, new: &Signed<Root>) -> Result<()> {
+    let mut ok = true;
+    for (label, trusted) in &[("Old", &old.signed), ("New", &new.signed)] {
+        let role_keys = trusted
+            .roles
+            .get(&RoleType::Root)
+            .context(error::RoleMissing {
+                role: RoleType::Root,
+            })?;
+        let signers = trusted.valid_signers(new).context(error::VerifySigned {
+            role: RoleType::Root,
+        })?;
+        println!(
+            "{} root (version {}) keys, threshold {}:",
+            label, trusted.version, role_keys.threshold
+        );
+        for key_id in &role_keys.keyids {
+            println!(
+                "  {:<8} {}",
+                if signers.contains(key_id) {
+                    "signed"
+                } else {
+                    "missing"
+                },
+                hex::encode(key_id)
+            );
+        }
+        let valid = signers.len() as u64;
+        let needed = role_keys.threshold.get().saturating_sub(valid);
+        println!(
+            "  {} of {} required signatures; {} more needed",
+            valid, role_keys.threshold, needed
+        );
+        ok &= needed == 0;
+    }
+
+    let expected = old.signed.version.get().checked_add(1);
+    if Some(new.signed.version.get()) == expected {
+        println!("Version: {} -> {}", old.signed.version, new.signed.version);
+    } else {
+        println!(
+            "Version: {} -> {} (must be exactly {})",
+            old.signed.version,
+            new.signed.version,
+            old.signed.version.get().saturating_add(1)
+        );
+        ok = false;
+    }
+
+    ensure!(ok, error::RotationRejected);
+    Ok(())
+}
+
+fn format_key(key: &Key, format: KeyFormat, key_id: &Decoded<Hex>) -> Result<String> {
+    let exported = match format {
+        KeyFormat::Pem => key.to_spki_pem(),
+        KeyFormat::OpenSsh => key.to_openssh(),
+        KeyFormat::Jwk => key
+            .to_jwk()
+            .map(|jwk| serde_json::to_string_pretty(&jwk))
+            .transpose()
+            .context(error::KeyExportJson)?,
+        KeyFormat::TufJson => {
+            Some(serde_json::to_string_pretty(key).context(error::KeyExportJson)?)
         }
+    };
+    exported.context(error::KeyExportUnsupported {
+        key_id: hex::encode(key_id),
+    })
+}
+
+/// Prints each key in root.json with its type, scheme, the roles it is listed for, and the SHA-256
+/// digest of its DER-encoded `SubjectPublicKeyInfo`, which is what most tools that show a
+/// "fingerprint" of a non-SSH public key compute.
+fn list_keys(path: &Path) -> Result<()> {
+    let root: Signed<Root> = load_file(path)?;
+    let root = &root.signed;
+    let mut keys = root.keys.iter().collect::<Vec<_>>();
+    keys.sort_by_key(|&(key_id, _)| key_id);
+    for (key_id, key) in keys {
+        let mut roles = root
+            .roles
+            .iter()
+            .filter(|(_, role_keys)| role_keys.keyids.contains(key_id))
+            .map(|(role, _)| role.to_string())
+            .collect::<Vec<_>>();
+        roles.sort();
+        let fingerprint = key.to_spki_der().map_or_else(
+            || "-".to_owned(),
+            |spki| format!("SHA256:{}", hex::encode(Sha256::digest(&spki))),
+        );
+        println!(
+            "{}  {:<8} {:<20} {:<32} {}",
+            hex::encode(key_id),
+            key_field(key, "keytype"),
+            key_field(key, "scheme"),
+            if roles.is_empty() {
+                "-".to_owned()
+            } else {
+                roles.join(",")
+            },
+            fingerprint
+        );
     }
+    Ok(())
 }
 
 fn round_time(time: DateTime<Utc>) -> DateTime<Utc> {
@@ -242,8 +558,23 @@ fn clear_sigs<T>(role: &mut Signed<T>) {
     role.signatures.clear();
 }
 
+fn parse_rsa_scheme(s: &str) -> serde_json::Result<RsaScheme> {
+    serde_json::from_value(serde_json::Value::String(s.to_owned()))
+}
+
+/// Overrides the default signature scheme of an RSA key.
+fn set_rsa_scheme(key: &mut Key, rsa_scheme: Option<&RsaScheme>) -> Result<()> {
+    if let Some(rsa_scheme) = rsa_scheme {
+        match key {
+            Key::Rsa { scheme, .. } => *scheme = rsa_scheme.clone(),
+            _ => return error::RsaSchemeNotRsa.fail(),
+        }
+    }
+    Ok(())
+}
+
 /// Adds a key to the root role if not already present, and adds its key ID to the specified role.
-fn add_key(root: &mut Root, role: &[RoleType], key: Key) -> Result<Decoded<Hex>> {
+fn add_key_to_root(root: &mut Root, role: &[RoleType], key: Key) -> Result<Decoded<Hex>> {
     let key_id = if let Some((key_id, _)) = root
         .keys
         .iter()
@@ -1,16 +1,21 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::error::Result;
-use crate::key::{keys_for_root, sign_metadata_inner};
+use crate::error::{self, Result};
+use crate::key::{canonical_payload, keys_for_root, sign_metadata_inner, sign_payload};
 use crate::source::KeySource;
 use crate::{load_file, write_file};
 use ring::rand::SystemRandom;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use tough::schema::{RoleType, Root, Signed};
+use tough::schema::decoded::{Decoded, Hex};
+use tough::schema::{RoleType, Root, Signature, Signed};
 
 #[derive(Debug, StructOpt)]
 pub(crate) struct SignArgs {
@@ -22,23 +27,57 @@ pub(crate) struct SignArgs {
     #[structopt(short = "k", long = "key")]
     keys: Vec<KeySource>,
 
-    /// Metadata file to sign
+    /// Write the canonical JSON payload of the metadata file to this path, and a manifest
+    /// describing it to PATH.manifest.json, instead of signing
+    #[structopt(long = "export-payload", conflicts_with = "detached")]
+    export_payload: Option<PathBuf>,
+
+    /// Treat the input as a payload written by --export-payload and output only the signatures
+    #[structopt(long = "detached")]
+    detached: bool,
+
+    /// Where to write detached signatures (default: stdout)
+    #[structopt(short = "o", long = "output", requires = "detached")]
+    output: Option<PathBuf>,
+
+    /// Metadata file to sign (or payload file, with --detached)
     metadata_file: PathBuf,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct PartialRole {
+pub(crate) struct PartialRole {
     #[serde(rename = "_type")]
-    type_: RoleType,
+    pub(crate) type_: RoleType,
 
     #[serde(flatten)]
     args: HashMap<String, serde_json::Value>,
 }
 
+/// Describes an exported payload so that offline signers can check what they are signing.
+#[derive(Debug, Serialize, Deserialize)]
+struct PayloadManifest {
+    /// The metadata file the payload was exported from
+    metadata: PathBuf,
+    role: RoleType,
+    length: u64,
+    sha256: Decoded<Hex>,
+    /// Signing threshold and key IDs for the role, according to root.json
+    threshold: Option<NonZeroU64>,
+    keyids: Vec<Decoded<Hex>>,
+}
+
 impl SignArgs {
     pub(crate) fn run(&self) -> Result<()> {
         let root: Signed<Root> = load_file(&self.root)?;
+        if let Some(payload_path) = &self.export_payload {
+            return self.export(&root.signed, payload_path);
+        }
+
         let keys = keys_for_root(&self.keys, &root.signed)?;
+        if self.detached {
+            return self.sign_detached(&root.signed, &keys);
+        }
+
         let mut metadata: Signed<PartialRole> = load_file(&self.metadata_file)?;
         sign_metadata_inner(
             &root.signed,
@@ -49,4 +88,297 @@ impl SignArgs {
         )?;
         write_file(&self.metadata_file, &metadata)
     }
+
+    fn export(&self, root: &Root, payload_path: &Path) -> Result<()> {
+        let metadata: Signed<PartialRole> = load_file(&self.metadata_file)?;
+        let payload = canonical_payload(&metadata.signed)?;
+        // The payload is written byte-for-byte; any trailing newline would change what is signed.
+        std::fs::write(payload_path, &payload).context(error::FileWrite { path: payload_path })?;
+
+        let role_keys = root.roles.get(&metadata.signed.type_);
+        let manifest = PayloadManifest {
+            metadata: self.metadata_file.clone(),
+            role: metadata.signed.type_,
+            length: payload.len() as u64,
+            sha256: Sha256::digest(&payload).as_slice().to_vec().into(),
+            threshold: role_keys.map(|role_keys| role_keys.threshold),
+            keyids: role_keys
+                .map(|role_keys| role_keys.keyids.clone())
+                .unwrap_or_default(),
+        };
+        let mut manifest_path = OsString::from(payload_path);
+        manifest_path.push(".manifest.json");
+        write_file(Path::new(&manifest_path), &manifest)
+    }
+
+    fn sign_detached(&self, root: &Root, keys: &crate::key::RootKeys) -> Result<()> {
+        let path = &self.metadata_file;
+        let payload = std::fs::read(path).context(error::FileRead { path })?;
+        let role: PartialRole =
+            olpc_cjson::from_slice(&payload).context(error::FileParseJson { path })?;
+        // Signing anything other than the exact canonical form would produce signatures that no
+        // client can verify.
+        ensure!(
+            canonical_payload(&role)? == payload,
+            error::PayloadNotCanonical { path }
+        );
+
+        let signatures = sign_payload(root, keys, role.type_, &payload, &SystemRandom::new())?;
+        ensure!(
+            !signatures.is_empty(),
+            error::NoSigningKeys { role: role.type_ }
+        );
+        if let Some(output) = &self.output {
+            write_file(output, &signatures)
+        } else {
+            serde_json::to_writer_pretty(std::io::stdout(), &signatures)
+                .context(error::WriteJsonStdout)?;
+            println!();
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct MergeSignaturesArgs {
+    /// Path to root.json file for the repository
+    #[structopt(short = "r", long = "root")]
+    root: PathBuf,
+
+    /// Metadata file to add the signatures to
+    metadata_file: PathBuf,
+
+    /// Files containing detached signatures (the output of `tuftool sign --detached`)
+    #[structopt(required = true)]
+    signatures: Vec<PathBuf>,
+}
+
+impl MergeSignaturesArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let root: Signed<Root> = load_file(&self.root)?;
+        let mut metadata: Signed<PartialRole> = load_file(&self.metadata_file)?;
+        let role = metadata.signed.type_;
+        let role_keys = root
+            .signed
+            .roles
+            .get(&role)
+            .context(error::RoleMissing { role })?;
+        let payload = canonical_payload(&metadata.signed)?;
+        // Whether a signature was made over this payload by a key root.json trusts for the role.
+        let verifies = |signature: &Signature| match root.signed.keys.get(&signature.keyid) {
+            Some(key) if role_keys.keyids.contains(&signature.keyid) => {
+                key.verify(&payload, &signature.sig)
+            }
+            _ => false,
+        };
+
+        let mut added = 0;
+        for path in &self.signatures {
+            let signatures: Vec<Signature> = load_file(path)?;
+            for signature in signatures {
+                let keyid = hex::encode(&signature.keyid);
+                let key = root
+                    .signed
+                    .keys
+                    .get(&signature.keyid)
+                    .filter(|_| role_keys.keyids.contains(&signature.keyid))
+                    .context(error::SignatureKeyUnknown {
+                        keyid: &keyid,
+                        path,
+                        role,
+                    })?;
+                ensure!(
+                    key.verify(&payload, &signature.sig),
+                    error::SignatureInvalid { keyid, path }
+                );
+                if !metadata
+                    .signatures
+                    .iter()
+                    .any(|existing| existing.keyid == signature.keyid && verifies(existing))
+                {
+                    // Replace any stale signature by the same key.
+                    metadata
+                        .signatures
+                        .retain(|existing| existing.keyid != signature.keyid);
+                    metadata.signatures.push(signature);
+                    added += 1;
+                }
+            }
+        }
+
+        let valid = metadata
+            .signatures
+            .iter()
+            .filter(|signature| verifies(signature))
+            .map(|signature| &signature.keyid)
+            .collect::<HashSet<_>>()
+            .len();
+        let unverified = metadata
+            .signatures
+            .iter()
+            .filter(|signature| !verifies(signature))
+            .count();
+        println!(
+            "Added {} signature(s); {} of {} required signatures present",
+            added, valid, role_keys.threshold
+        );
+        if unverified > 0 {
+            println!(
+                "{} existing signature(s) do not verify and were not counted",
+                unverified
+            );
+        }
+        write_file(&self.metadata_file, &metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeSignaturesArgs, SignArgs};
+    use crate::key::KeyPair;
+    use crate::source::KeySource;
+    use crate::write_file;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+    use std::collections::HashMap;
+    use std::num::NonZeroU64;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+    use tough::schema::decoded::{Decoded, Hex};
+    use tough::schema::{RoleKeys, RoleType, Root, Signature, Signed, Targets};
+
+    /// Generates a P-256 key, writes it to `path`, and returns its public key.
+    fn gen_key(path: &Path) -> tough::schema::key::Key {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+                .unwrap();
+        let pem = pem::encode(&pem::Pem {
+            tag: "PRIVATE KEY".to_owned(),
+            contents: pkcs8.as_ref().to_vec(),
+        });
+        std::fs::write(path, &pem).unwrap();
+        KeyPair::parse(pem.as_bytes()).unwrap().public_key()
+    }
+
+    /// Writes a.pem, b.pem, and a root.json in `dir` where targets needs signatures from both keys.
+    fn write_root(dir: &Path) -> (Root, Vec<Decoded<Hex>>) {
+        let path = |name: &str| dir.join(name);
+        let mut keys = HashMap::new();
+        let mut keyids = Vec::new();
+        for name in &["a.pem", "b.pem"] {
+            let key = gen_key(&path(name));
+            let keyid = key.key_id().unwrap();
+            keyids.push(keyid.clone());
+            keys.insert(keyid, key);
+        }
+        let role_keys = |threshold| RoleKeys {
+            keyids: keyids.clone(),
+            threshold: NonZeroU64::new(threshold).unwrap(),
+            _extra: HashMap::new(),
+        };
+        let mut roles = HashMap::new();
+        roles.insert(RoleType::Root, role_keys(1));
+        roles.insert(RoleType::Targets, role_keys(2));
+        let root = Root {
+            spec_version: crate::SPEC_VERSION.to_owned(),
+            consistent_snapshot: true,
+            version: NonZeroU64::new(1).unwrap(),
+            expires: "2030-01-01T00:00:00Z".parse().unwrap(),
+            keys,
+            roles,
+            _extra: HashMap::new(),
+        };
+        write_file(
+            &path("root.json"),
+            &Signed {
+                signed: root.clone(),
+                signatures: Vec::new(),
+            },
+        )
+        .unwrap();
+        (root, keyids)
+    }
+
+    #[test]
+    fn export_sign_detached_merge() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let (root, keyids) = write_root(dir.path());
+
+        // The metadata starts out with a bogus signature by the second key, which must not count
+        // toward the threshold and must be replaced when a real one is merged.
+        let bogus = Signature {
+            keyid: keyids[1].clone(),
+            sig: vec![0; 64].into(),
+        };
+        std::fs::write(
+            path("targets.json"),
+            serde_json::json!({
+                "signed": {
+                    "_type": "targets",
+                    "spec_version": crate::SPEC_VERSION,
+                    "version": 1,
+                    "expires": "2030-01-01T00:00:00Z",
+                    "targets": {},
+                },
+                "signatures": [bogus],
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        SignArgs {
+            root: path("root.json"),
+            keys: Vec::new(),
+            export_payload: Some(path("payload")),
+            detached: false,
+            output: None,
+            metadata_file: path("targets.json"),
+        }
+        .run()
+        .unwrap();
+        assert!(path("payload.manifest.json").exists());
+        for (key, output) in &[("a.pem", "a.sig"), ("b.pem", "b.sig")] {
+            SignArgs {
+                root: path("root.json"),
+                keys: vec![KeySource::Local(path(key))],
+                export_payload: None,
+                detached: true,
+                output: Some(path(output)),
+                metadata_file: path("payload"),
+            }
+            .run()
+            .unwrap();
+        }
+
+        let merge = |signatures: &[&str]| {
+            MergeSignaturesArgs {
+                root: path("root.json"),
+                metadata_file: path("targets.json"),
+                signatures: signatures
+                    .iter()
+                    .map(|name| path(name))
+                    .collect::<Vec<PathBuf>>(),
+            }
+            .run()
+            .unwrap();
+            serde_json::from_slice::<Signed<Targets>>(&std::fs::read(path("targets.json")).unwrap())
+                .unwrap()
+        };
+
+        let targets = merge(&["a.sig"]);
+        assert_eq!(targets.signatures.len(), 2);
+        assert_eq!(
+            root.valid_signers(&targets).unwrap(),
+            vec![keyids[0].clone()]
+        );
+        root.verify_role(&targets).unwrap_err();
+
+        // Merging the same signature again is a no-op; merging the second key's replaces the
+        // bogus one.
+        let targets = merge(&["a.sig", "b.sig"]);
+        assert_eq!(targets.signatures.len(), 2);
+        assert!(!targets.signatures.contains(&bogus));
+        root.verify_role(&targets).unwrap();
+    }
 }
@@ -0,0 +1,240 @@
+// Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Checks that a repository on disk is internally consistent, as a client would see it.
+
+use crate::error::{self, Result};
+use crate::load_file;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use tempfile::tempdir;
+use tough::schema::{Root, Signed};
+use tough::{Limits, Repository, SchemeTransport, Settings};
+use url::Url;
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct VerifyArgs {
+    /// Path to the trusted root.json file for the repository
+    root: PathBuf,
+
+    /// Repository directory (the `outdir` of `tuftool create`)
+    repo: PathBuf,
+}
+
+/// Collects the result of each check so a full report can be printed.
+#[derive(Debug, Default)]
+struct Report {
+    failures: usize,
+    checks: usize,
+}
+
+impl Report {
+    fn check<E: Display>(&mut self, what: &str, result: std::result::Result<(), E>) -> bool {
+        self.checks += 1;
+        match result {
+            Ok(()) => {
+                println!("PASS  {}", what);
+                true
+            }
+            Err(err) => {
+                self.failures += 1;
+                println!("FAIL  {}: {}", what, err);
+                false
+            }
+        }
+    }
+}
+
+impl VerifyArgs {
+    pub(crate) fn run(&self) -> Result<()> {
+        let repo =
+            std::fs::canonicalize(&self.repo).context(error::FileOpen { path: &self.repo })?;
+        let metadir = repo.join("metadata");
+        let mut report = Report::default();
+
+        self.verify_root_chain(&metadir, &mut report)?;
+
+        let transport = SchemeTransport::new();
+        let datastore = tempdir().context(error::TempDir)?;
+        let metadata_base_url = dir_url(&metadir)?;
+        let target_base_url = dir_url(&repo.join("targets"))?;
+        let repository = Repository::load(
+            &transport,
+            Settings {
+                root: File::open(&self.root).context(error::OpenRoot { path: &self.root })?,
+                datastore: datastore.path(),
+                metadata_base_url: &metadata_base_url,
+                target_base_url: &target_base_url,
+                limits: Limits::default(),
+                observer: None,
+            },
+        );
+        let load_result = repository.as_ref().map(|_| ());
+        if report.check("load repository metadata", load_result) {
+            if let Ok(repository) = &repository {
+                let mut targets = repository.targets().iter().collect::<Vec<_>>();
+                targets.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, target) in targets {
+                    report.check(
+                        &format!("target {}", name),
+                        verify_target(repository, name, target.length),
+                    );
+                }
+            }
+        }
+
+        println!(
+            "{} of {} checks passed",
+            report.checks - report.failures,
+            report.checks
+        );
+        ensure!(
+            report.failures == 0,
+            error::VerifyFailed {
+                failures: report.failures
+            }
+        );
+        Ok(())
+    }
+
+    /// Checks every link in the chain of `N.root.json` files, starting from the trusted root.
+    ///
+    /// A root file that can't be read or parsed is reported as a failed check; like a link that
+    /// fails verification, it ends the chain.
+    fn verify_root_chain(&self, metadir: &Path, report: &mut Report) -> Result<()> {
+        let mut root: Signed<Root> = match load_file(&self.root) {
+            Ok(root) => root,
+            Err(err) => {
+                report.check("trusted root", Err(err));
+                return Ok(());
+            }
+        };
+        report.check(
+            &format!("trusted root version {}", root.signed.version),
+            root.signed.verify_role(&root),
+        );
+
+        loop {
+            let path = metadir.join(format!("{}.root.json", root.signed.version.get() + 1));
+            if !path.exists() {
+                break;
+            }
+            let what = format!(
+                "root version {} -> {}",
+                root.signed.version,
+                root.signed.version.get() + 1
+            );
+            // Clients cannot trust anything past a broken link.
+            let new_root: Signed<Root> = match load_file(&path) {
+                Ok(new_root) => new_root,
+                Err(err) => {
+                    report.check(&what, Err(err));
+                    return Ok(());
+                }
+            };
+            if !report.check(&what, verify_root_link(&root, &new_root)) {
+                return Ok(());
+            }
+            root = new_root;
+        }
+
+        // Anything newer than the end of the chain is unreachable by clients.
+        for entry in std::fs::read_dir(metadir).context(error::FileRead { path: metadir })? {
+            let entry = entry.context(error::FileRead { path: metadir })?;
+            let version = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".root.json"))
+                .and_then(|version| version.parse::<u64>().ok());
+            if let Some(version) = version {
+                if version > root.signed.version.get() {
+                    report.check(
+                        &format!("root version {}", version),
+                        Err(format!(
+                            "unreachable; chain ends at version {}",
+                            root.signed.version
+                        )),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Performs the checks from step 1.3 and 1.4 of the client workflow on a new root.
+fn verify_root_link(old: &Signed<Root>, new: &Signed<Root>) -> std::result::Result<(), String> {
+    old.signed
+        .verify_role(new)
+        .map_err(|err| format!("not signed by previous root: {}", err))?;
+    new.signed
+        .verify_role(new)
+        .map_err(|err| format!("not signed by itself: {}", err))?;
+    if new.signed.version.get() != old.signed.version.get() + 1 {
+        return Err(format!("contains version {}", new.signed.version));
+    }
+    Ok(())
+}
+
+/// Reads a target through the repository, which checks its hash, and checks its exact length.
+fn verify_target(
+    repository: &Repository<'_, SchemeTransport>,
+    name: &str,
+    length: u64,
+) -> std::result::Result<(), String> {
+    let mut reader = repository
+        .read_target(name)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "not listed in metadata".to_owned())?;
+    let read = io::copy(&mut reader, &mut io::sink()).map_err(|err| err.to_string())?;
+    if read != length {
+        return Err(format!("length is {}, expected {}", read, length));
+    }
+    Ok(())
+}
+
+fn dir_url(path: &Path) -> Result<String> {
+    Url::from_directory_path(path)
+        .map(|url| url.to_string())
+        .ok()
+        .context(error::UrlFromPath { path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Report, VerifyArgs};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn reference_metadata() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../tough/tests/data/tuf-reference-impl/metadata")
+    }
+
+    #[test]
+    fn unreadable_root_is_a_failed_check() {
+        let metadir = tempdir().unwrap();
+        std::fs::write(metadir.path().join("2.root.json"), "not json").unwrap();
+        let args = VerifyArgs {
+            root: reference_metadata().join("1.root.json"),
+            repo: PathBuf::new(),
+        };
+        let mut report = Report::default();
+        args.verify_root_chain(metadir.path(), &mut report).unwrap();
+        assert_eq!(report.checks, 2);
+        assert_eq!(report.failures, 1);
+
+        let args = VerifyArgs {
+            root: metadir.path().join("missing.root.json"),
+            repo: PathBuf::new(),
+        };
+        let mut report = Report::default();
+        args.verify_root_chain(metadir.path(), &mut report).unwrap();
+        assert_eq!(report.checks, 1);
+        assert_eq!(report.failures, 1);
+    }
+}